@@ -0,0 +1,131 @@
+//! Helpers for [`Data::copy_asset`](crate::Data::copy_asset) implementations.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::page::fnv1a;
+
+/// Returns a file name for the asset `src` derived from its content, which
+/// keeps its extension (`3f2a9c0e1b7d4a65.png`).
+///
+/// Assets with the same content get the same name, so they are only stored
+/// once in the vault.
+pub fn hashed_file_name(src: &Path) -> Result<String> {
+    let content =
+        std::fs::read(src).with_context(|| anyhow!("Could not read '{}'", src.display()))?;
+    let hash = fnv1a(&content);
+    Ok(match src.extension() {
+        Some(ext) => format!("{hash:016x}.{}", ext.to_string_lossy()),
+        None => format!("{hash:016x}"),
+    })
+}
+
+/// Copies the assets of `plan`, as returned by
+/// [`Data::asset_plan`](crate::Data::asset_plan), creating their directories.
+///
+/// Relative destinations are relative to `out_dir`.
+pub fn execute_asset_copies(plan: &[(PathBuf, PathBuf)], out_dir: &Path) -> Result<()> {
+    for (src, dest) in plan {
+        let dest = out_dir.join(dest);
+        let context = || anyhow!("Could not copy '{}' to '{}'", src.display(), dest.display());
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent).with_context(context)?;
+        }
+        std::fs::copy(src, &dest).with_context(context)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+struct HashingData {
+    page_dir: std::path::PathBuf,
+    copied: Vec<String>,
+    plan: Vec<(PathBuf, PathBuf)>,
+}
+
+#[cfg(test)]
+impl crate::Data for HashingData {
+    fn page_title(&mut self, _title: &str) {}
+    fn copy_asset(&mut self, path: &str) -> Result<String> {
+        let name = hashed_file_name(&self.page_dir.join(path))?;
+        self.copied.push(name.clone());
+        let link = format!("attachments/{name}");
+        self.plan
+            .push((self.page_dir.join(path), PathBuf::from(&link)));
+        Ok(link)
+    }
+    fn asset_plan(&self) -> &[(PathBuf, PathBuf)] {
+        &self.plan
+    }
+    fn register_id(&mut self, _id: &crate::Id) {}
+    fn query_id(&self, _logseq_id: &str) -> Option<&crate::Ref> {
+        None
+    }
+    fn curr_title(&self) -> &str {
+        ""
+    }
+}
+
+#[test]
+fn test_hashed_file_name() {
+    let dir = std::env::temp_dir().join("logseq2obsdn-hashed-file-name");
+    std::fs::create_dir_all(dir.join("assets")).unwrap();
+    std::fs::create_dir_all(dir.join("pages")).unwrap();
+    std::fs::write(dir.join("assets/a.png"), "image").unwrap();
+    std::fs::write(dir.join("assets/b.png"), "image").unwrap();
+
+    let mut data = HashingData {
+        page_dir: dir.join("pages"),
+        copied: vec![],
+        plan: vec![],
+    };
+    let out =
+        crate::convert_page("- ![a](../assets/a.png) ![b](../assets/b.png)", &mut data).unwrap();
+
+    let name = format!("{:016x}.png", fnv1a(b"image"));
+    assert_eq!(data.copied, [name.clone(), name.clone()]);
+    assert_eq!(
+        out,
+        format!("- ![a](attachments/{name}) ![b](attachments/{name})\n")
+    );
+}
+
+#[test]
+fn test_asset_plan() {
+    let dir = std::env::temp_dir().join("logseq2obsdn-asset-plan");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("assets")).unwrap();
+    std::fs::create_dir_all(dir.join("pages")).unwrap();
+    std::fs::write(dir.join("assets/a.png"), "a").unwrap();
+    std::fs::write(dir.join("assets/b.jpg"), "b").unwrap();
+
+    let mut data = HashingData {
+        page_dir: dir.join("pages"),
+        copied: vec![],
+        plan: vec![],
+    };
+    crate::convert_page(
+        "- ![a](../assets/a.png)\n- ![b](../assets/b.jpg)",
+        &mut data,
+    )
+    .unwrap();
+
+    let plan = crate::Data::asset_plan(&data).to_vec();
+    let dests = plan.iter().map(|(_, d)| d.clone()).collect::<Vec<_>>();
+    assert_eq!(
+        dests,
+        [
+            Path::new("attachments").join(format!("{:016x}.png", fnv1a(b"a"))),
+            Path::new("attachments").join(format!("{:016x}.jpg", fnv1a(b"b"))),
+        ]
+    );
+    assert_eq!(plan[0].0, dir.join("pages/../assets/a.png"));
+
+    execute_asset_copies(&plan, &dir.join("vault")).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(dir.join("vault").join(&dests[1])).unwrap(),
+        "b"
+    );
+    assert!(execute_asset_copies(&[(dir.join("missing.png"), dests[0].clone())], &dir).is_err());
+}
@@ -0,0 +1,115 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use clap::{ArgMatches, ValueSource};
+use logseq2obsdn::{AnchorStyle, BlockProps, CalloutStyle, ClozeStyle};
+
+use crate::Args;
+
+/// Options read from the JSON file passed with `--config`, each mirrors the CLI
+/// option of the same name.
+///
+/// The format is JSON by design, it's read with the `serde_json` the ids file
+/// already needs.
+#[derive(serde::Deserialize, Default)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    extract_ids: Option<bool>,
+    convert_all: Option<bool>,
+    journals: Option<PathBuf>,
+    graph: Option<bool>,
+    exclude: Option<Vec<String>>,
+    include: Option<Vec<String>>,
+    journal_format: Option<String>,
+    flatten_journals_into: Option<String>,
+    merge_namespace: Option<String>,
+    export_single: Option<PathBuf>,
+    no_task_dates: Option<bool>,
+    strike_done: Option<bool>,
+    rich_task_states: Option<bool>,
+    keep_logseq_ids: Option<bool>,
+    inline_refs: Option<bool>,
+    namespaces: Option<bool>,
+    callout_style: Option<CalloutStyle>,
+    cloze_style: Option<ClozeStyle>,
+    block_props: Option<BlockProps>,
+    anchor_style: Option<AnchorStyle>,
+    drop_queries: Option<bool>,
+    drop_renderers: Option<bool>,
+    drop_dynamic: Option<bool>,
+    hard_breaks: Option<bool>,
+    keep_bold_headers_as_list: Option<bool>,
+    image_embeds: Option<bool>,
+    captions_below: Option<bool>,
+    italic_captions: Option<bool>,
+    tags_as_links: Option<bool>,
+    html_highlights: Option<bool>,
+    flatten_empty_groups: Option<bool>,
+    public_as_publish: Option<bool>,
+    title_heading: Option<bool>,
+    hoist_block_tags: Option<bool>,
+    trim_trailing_whitespace: Option<bool>,
+    ids_file: Option<PathBuf>,
+    strict: Option<bool>,
+    attachments_dir: Option<PathBuf>,
+    preserve_asset_tree: Option<bool>,
+    assets_dir: Option<PathBuf>,
+    routes: Option<BTreeMap<String, PathBuf>>,
+    replacements: Option<Vec<(String, String)>>,
+    link_assets: Option<bool>,
+    dry_run: Option<bool>,
+    check: Option<bool>,
+    jobs: Option<usize>,
+    max_depth: Option<usize>,
+    quiet: Option<bool>,
+    verbose: Option<bool>,
+    force: Option<bool>,
+    stdout: Option<bool>,
+    out_extension: Option<String>,
+    report_json: Option<PathBuf>,
+    emit_mapping: Option<PathBuf>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| anyhow!("Could not read the config '{}'", path.display()))?;
+        serde_json::from_str(&text).with_context(|| anyhow!("Invalid config '{}'", path.display()))
+    }
+
+    /// Sets the options of `args` that weren't passed on the command line
+    /// according to `matches`.
+    pub fn apply(self, args: &mut Args, matches: &ArgMatches) {
+        // The ids of the args are the kebab-case field names.
+        let from_cli = |field: &str| {
+            matches.value_source(field.replace('_', "-")) == Some(ValueSource::CommandLine)
+        };
+        macro_rules! apply {
+            ($($field:ident),* ; $($opt:ident),*) => {
+                $(if let Some(v) = self.$field {
+                    if !from_cli(stringify!($field)) {
+                        args.$field = v;
+                    }
+                })*
+                $(if self.$opt.is_some() && !from_cli(stringify!($opt)) {
+                    args.$opt = self.$opt;
+                })*
+            };
+        }
+        apply!(
+            extract_ids, convert_all, graph, exclude, include, journal_format, no_task_dates, strike_done, rich_task_states, keep_logseq_ids,
+            inline_refs, namespaces, callout_style, cloze_style, block_props, anchor_style, drop_queries, drop_renderers, drop_dynamic, hard_breaks,
+            keep_bold_headers_as_list, image_embeds, captions_below, italic_captions, tags_as_links, html_highlights, flatten_empty_groups, public_as_publish, title_heading, hoist_block_tags, trim_trailing_whitespace, strict, link_assets, preserve_asset_tree,
+            dry_run, check, jobs, max_depth, quiet, verbose, force, stdout, out_extension;
+            journals, flatten_journals_into, merge_namespace, export_single, ids_file, attachments_dir, assets_dir, report_json, emit_mapping
+        );
+        // Routes and replacements can only be set here.
+        if let Some(routes) = self.routes {
+            args.routes = routes;
+        }
+        if let Some(replacements) = self.replacements {
+            args.replacements = replacements;
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! Glob patterns for filtering the scanned pages.
+
+use std::path::Path;
+
+/// A glob pattern matched against `/` separated relative paths.
+///
+/// `*` and `?` match any characters but `/`, `**` matches any number of
+/// directories.
+#[derive(Clone, Debug)]
+pub struct Glob(Vec<char>);
+
+impl Glob {
+    pub fn new(pattern: &str) -> Glob {
+        Glob(pattern.trim_end_matches('/').chars().collect())
+    }
+
+    pub fn is_match(&self, path: &Path) -> bool {
+        let path = path
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        matches(&self.0, &path.chars().collect::<Vec<_>>())
+    }
+}
+
+fn matches(pattern: &[char], s: &[char]) -> bool {
+    match pattern {
+        [] => s.is_empty(),
+        // `**/` only matches whole directories.
+        ['*', '*', '/', rest @ ..] => (0..=s.len())
+            .filter(|&i| i == 0 || s[i - 1] == '/')
+            .any(|i| matches(rest, &s[i..])),
+        ['*', '*', rest @ ..] => (0..=s.len()).any(|i| matches(rest, &s[i..])),
+        ['*', rest @ ..] => {
+            let end = s.iter().position(|c| *c == '/').unwrap_or(s.len());
+            (0..=end).any(|i| matches(rest, &s[i..]))
+        }
+        ['?', rest @ ..] => s.first().is_some_and(|c| *c != '/') && matches(rest, &s[1..]),
+        [c, rest @ ..] => s.first() == Some(c) && matches(rest, &s[1..]),
+    }
+}
+
+/// The `--include` and `--exclude` patterns of the scanned pages.
+#[derive(Clone, Debug, Default)]
+pub struct PathFilter {
+    pub include: Vec<Glob>,
+    pub exclude: Vec<Glob>,
+}
+
+impl PathFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> PathFilter {
+        PathFilter {
+            include: include.iter().map(|p| Glob::new(p)).collect(),
+            exclude: exclude.iter().map(|p| Glob::new(p)).collect(),
+        }
+    }
+
+    /// Whether the directory `path` relative to the scanned directory is excluded.
+    pub fn excludes_dir(&self, path: &Path) -> bool {
+        self.exclude.iter().any(|g| g.is_match(path))
+    }
+
+    /// Whether the file `path` relative to the scanned directory is scanned.
+    pub fn allows(&self, path: &Path) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|g| g.is_match(path)))
+            && !self.exclude.iter().any(|g| g.is_match(path))
+    }
+}
+
+#[test]
+fn test_glob() {
+    let is_match = |p: &str, s: &str| Glob::new(p).is_match(Path::new(s));
+    assert!(is_match("templates/**", "templates/a.md"));
+    assert!(is_match("templates/**", "templates/sub/a.md"));
+    assert!(is_match("templates/", "templates"));
+    assert!(!is_match("templates/**", "other/templates.md"));
+    assert!(is_match("**/archive/*.md", "archive/a.md"));
+    assert!(is_match("**/archive/*.md", "a/b/archive/a.md"));
+    assert!(!is_match("**/archive/*.md", "a/myarchive/a.md"));
+    assert!(!is_match("*.md", "sub/a.md"));
+    assert!(is_match("draft-?.md", "draft-1.md"));
+    assert!(!is_match("draft-?.md", "draft-10.md"));
+}
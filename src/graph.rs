@@ -0,0 +1,91 @@
+//! The directories of a logseq graph.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use once_cell::sync::OnceCell;
+use regex::Regex;
+
+/// Groups:
+/// 1: key (`pages` or `journals`)
+/// 2: directory
+fn directory_key_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| {
+        Regex::new(r#"(?m)^[^;\n]*?:(pages|journals)-directory\s+"([^"]*)""#).unwrap()
+    })
+}
+
+/// The directories of a logseq graph, the journals and assets only if they exist.
+#[derive(Debug, PartialEq, Eq)]
+pub struct GraphDirs {
+    pub pages: PathBuf,
+    pub journals: Option<PathBuf>,
+    pub assets: Option<PathBuf>,
+}
+
+impl GraphDirs {
+    /// Finds the directories of the graph at `root`, as configured by
+    /// `:pages-directory` and `:journals-directory` in its `logseq/config.edn`,
+    /// `pages`, `journals` and `assets` otherwise.
+    pub fn discover(root: &Path) -> Result<GraphDirs> {
+        let config = std::fs::read_to_string(root.join("logseq/config.edn")).unwrap_or_default();
+        let (mut pages, mut journals) = ("pages", "journals");
+        for c in directory_key_re().captures_iter(&config) {
+            match &c[1] {
+                "pages" => pages = c.get(2).unwrap().as_str(),
+                _ => journals = c.get(2).unwrap().as_str(),
+            }
+        }
+
+        let pages = root.join(pages);
+        if !pages.is_dir() {
+            bail!(
+                "'{}' is not a logseq graph, it has no '{}' directory",
+                root.display(),
+                pages.display()
+            );
+        }
+        Ok(GraphDirs {
+            pages,
+            journals: Some(root.join(journals)).filter(|p| p.is_dir()),
+            assets: Some(root.join("assets")).filter(|p| p.is_dir()),
+        })
+    }
+}
+
+#[test]
+fn test_discover() {
+    let root = std::env::temp_dir().join("logseq2obsdn-graph-dirs");
+    let _ = std::fs::remove_dir_all(&root);
+    for dir in ["logseq", "notes", "daily", "assets", "pages", "journals"] {
+        std::fs::create_dir_all(root.join(dir)).unwrap();
+    }
+
+    assert_eq!(
+        GraphDirs::discover(&root).unwrap(),
+        GraphDirs {
+            pages: root.join("pages"),
+            journals: Some(root.join("journals")),
+            assets: Some(root.join("assets")),
+        }
+    );
+
+    std::fs::write(
+        root.join("logseq/config.edn"),
+        "{:meta/version 1\n ;; :pages-directory \"old\"\n :pages-directory \"notes\"\n :journals-directory \"daily\"}",
+    )
+    .unwrap();
+    assert_eq!(
+        GraphDirs::discover(&root).unwrap(),
+        GraphDirs {
+            pages: root.join("notes"),
+            journals: Some(root.join("daily")),
+            assets: Some(root.join("assets")),
+        }
+    );
+
+    std::fs::remove_dir_all(root.join("daily")).unwrap();
+    assert_eq!(GraphDirs::discover(&root).unwrap().journals, None);
+    assert!(GraphDirs::discover(&root.join("logseq")).is_err());
+}
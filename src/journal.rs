@@ -0,0 +1,152 @@
+use once_cell::sync::OnceCell;
+use regex::Regex;
+
+fn journal_file_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| Regex::new(r"^(\d{4})_(\d{2})_(\d{2})$").unwrap())
+}
+
+fn iso_date_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| Regex::new(r"^\d{4}-\d{2}-\d{2}$").unwrap())
+}
+
+/// Groups:
+/// 1: month name
+/// 2: day
+/// 3: year
+fn month_day_year_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| {
+        Regex::new(r"^(?:[A-Za-z]+,\s+)?([A-Za-z]+)\.?\s+(\d{1,2})(?:st|nd|rd|th)?,?\s+(\d{4})$")
+            .unwrap()
+    })
+}
+
+/// Groups:
+/// 1: day
+/// 2: month name
+/// 3: year
+fn day_month_year_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| {
+        Regex::new(r"^(?:[A-Za-z]+,\s+)?(\d{1,2})(?:st|nd|rd|th)?\.?\s+([A-Za-z]+)\.?,?\s+(\d{4})$")
+            .unwrap()
+    })
+}
+
+/// Groups:
+/// 1: year
+/// 2: month
+/// 3: day
+fn year_month_day_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| Regex::new(r"^(\d{4})[-/_.](\d{1,2})[-/_.](\d{1,2})$").unwrap())
+}
+
+const MONTHS: [&str; 12] = [
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+/// Returns the number of the English month `name`, which may be abbreviated to
+/// its first three letters.
+fn month(name: &str) -> Option<u32> {
+    let name = name.to_lowercase();
+    let i = MONTHS
+        .iter()
+        .position(|m| *m == name || (name.len() >= 3 && m.starts_with(&name)))?;
+    Some(i as u32 + 1)
+}
+
+/// Parses the title of a logseq journal page as `(year, month, day)`.
+///
+/// Understands the English date formats logseq offers, like `Oct 5th, 2023`,
+/// `Thursday, October 5th, 2023`, `5 Oct 2023` or `2023/10/05`. Purely numeric
+/// dates other than year first are ambiguous and not parsed.
+pub fn parse_journal_date(title: &str) -> Option<(u32, u32, u32)> {
+    let title = title.trim();
+    let (year, month, day) = if let Some(c) = month_day_year_re().captures(title) {
+        (c[3].parse().ok()?, month(&c[1])?, c[2].parse().ok()?)
+    } else if let Some(c) = day_month_year_re().captures(title) {
+        (c[3].parse().ok()?, month(&c[2])?, c[1].parse().ok()?)
+    } else if let Some(c) = year_month_day_re().captures(title) {
+        (c[1].parse().ok()?, c[2].parse().ok()?, c[3].parse().ok()?)
+    } else {
+        return None;
+    };
+    ((1..=12).contains(&month) && (1..=31).contains(&day)).then_some((year, month, day))
+}
+
+/// Formats the date using `format`, where `YYYY`, `MM` and `DD` are replaced by
+/// the year, month and day.
+pub fn format_date(format: &str, (year, month, day): (u32, u32, u32)) -> String {
+    format
+        .replace("YYYY", &format!("{year:04}"))
+        .replace("MM", &format!("{month:02}"))
+        .replace("DD", &format!("{day:02}"))
+}
+
+/// Returns the title of the journal with the file stem `file_stem`.
+///
+/// Logseq names journals `YYYY_MM_DD`, these are formatted using `format` where
+/// `YYYY`, `MM` and `DD` are replaced by the year, month and day. Journals already
+/// named with an ISO date are returned unchanged. Returns `None` if the file name
+/// is not a date.
+pub fn journal_title(file_stem: &str, format: &str) -> Option<String> {
+    if iso_date_re().is_match(file_stem) {
+        return Some(file_stem.to_owned());
+    }
+
+    let c = journal_file_re().captures(file_stem)?;
+    let date = (c[1].parse().ok()?, c[2].parse().ok()?, c[3].parse().ok()?);
+    Some(format_date(format, date))
+}
+
+#[test]
+fn test_journal_title() {
+    assert_eq!(
+        journal_title("2023_10_05", "YYYY-MM-DD").as_deref(),
+        Some("2023-10-05")
+    );
+    assert_eq!(
+        journal_title("2023_10_05", "DD.MM.YYYY").as_deref(),
+        Some("05.10.2023")
+    );
+    assert_eq!(
+        journal_title("2023-10-05", "DD.MM.YYYY").as_deref(),
+        Some("2023-10-05")
+    );
+    assert_eq!(journal_title("Meeting notes", "YYYY-MM-DD"), None);
+}
+
+#[test]
+fn test_parse_journal_date() {
+    for title in [
+        "Oct 5th, 2023",
+        "October 5th, 2023",
+        "Thursday, October 5th, 2023",
+        "Thu, Oct 5, 2023",
+        "5th Oct 2023",
+        "5 October, 2023",
+        "2023/10/05",
+        "2023-10-05",
+    ] {
+        assert_eq!(parse_journal_date(title), Some((2023, 10, 5)), "{title}");
+    }
+    assert_eq!(parse_journal_date("Sep 1st, 2023"), Some((2023, 9, 1)));
+    assert_eq!(parse_journal_date("Meeting notes"), None);
+    assert_eq!(parse_journal_date("Foo 5th, 2023"), None);
+    assert_eq!(parse_journal_date("Oct 35th, 2023"), None);
+    assert_eq!(parse_journal_date("10/05/2023"), None);
+}
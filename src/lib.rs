@@ -0,0 +1,26 @@
+//! Converts logseq pages to obsidian notes.
+//!
+//! The conversion is driven by a [`Data`] implementation, which decides where
+//! converted pages and their assets end up and resolves block references.
+
+use std::path::Path;
+
+use anyhow::Result;
+
+pub mod assets;
+pub mod page;
+pub mod postprocess;
+
+pub use page::{
+    AnchorStyle, Block, BlockProps, CalloutStyle, ClozeStyle, Config, Data, Id, Page, Ref, RefsFile,
+};
+pub use postprocess::PostProcess;
+
+/// Parses, transforms and renders the logseq page `input`.
+///
+/// The page is titled by its `title::` property.
+pub fn convert_page(input: &str, data: &mut dyn Data) -> Result<String> {
+    let mut page = Page::parse(Path::new(""), input, data)?;
+    page.transform(data);
+    Ok(page.to_string(data))
+}
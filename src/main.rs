@@ -1,51 +1,536 @@
-use std::io::{BufReader, BufWriter, Write};
-use std::path::{Path, PathBuf};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::{BufReader, BufWriter, IsTerminal, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 
-use anyhow::{anyhow, Context, Result};
-use clap::Parser;
-use page::RefsFile;
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{CommandFactory, FromArgMatches, Parser};
+use glob::PathFilter;
+use itertools::Itertools;
+use logseq2obsdn::page;
+use logseq2obsdn::postprocess::TrimTrailingWhitespace;
+use logseq2obsdn::{AnchorStyle, BlockProps, CalloutStyle, ClozeStyle, Ref, RefsFile};
+use manifest::Manifest;
 
-use crate::page::Ref;
+mod config;
+mod glob;
+mod graph;
+mod journal;
+mod manifest;
+mod nfc;
 
-mod page;
+/// The examples and notes of `--help`.
+const EXAMPLES: &str = "EXAMPLES:
+    Convert a whole graph, resolving the block references between its pages:
+        logseq2obsdn --convert-all <logseq-dir>/pages <vault>
 
+    Convert the pages, journals and assets of a graph root:
+        logseq2obsdn --graph --convert-all <logseq-dir> <vault>
+
+    Convert pages one at a time, after extracting the ids once:
+        logseq2obsdn --extract-ids <logseq-dir>/pages <vault>
+        logseq2obsdn <logseq-dir>/pages/<page>.md <vault>
+
+NOTES:
+    File names are composed to NFC, so that names written decomposed on macOS
+    match on Linux. Only precomposed Latin letters are composed, Greek, Cyrillic
+    and Hangul names keep their decomposed form.";
+
+/// Converts logseq pages to obsidian notes.
 #[derive(Parser)]
+#[clap(after_help = EXAMPLES)]
 struct Args {
+    /// The `pages` directory of the logseq graph, or a single page.
+    #[clap(value_name = "LOGSEQ_DIR_OR_PAGE")]
     file_or_folder: PathBuf,
+    /// The obsidian vault the converted pages are written to, created if needed.
+    #[clap(value_name = "VAULT")]
     vault: PathBuf,
+    /// Extract the block ids of all pages into the ids file. Ids already in the
+    /// file are kept, unless a page registers the same id again.
     #[clap(long)]
     extract_ids: bool,
+    /// Extract the ids of all pages in memory and convert them in one run.
+    #[clap(long)]
+    convert_all: bool,
+    /// Logseq journals directory, whose journals are converted alongside the pages.
+    #[clap(long)]
+    journals: Option<PathBuf>,
+    /// The input is the root of a logseq graph, whose pages, journals and
+    /// assets directories are found from its `logseq/config.edn`.
+    #[clap(long)]
+    graph: bool,
+    /// Skip the pages matching this glob, relative to the scanned directory
+    /// (e.g. `templates/**`). Can be passed multiple times.
+    #[clap(long)]
+    exclude: Vec<String>,
+    /// Only convert the pages matching this glob, relative to the scanned
+    /// directory. Can be passed multiple times.
+    #[clap(long)]
+    include: Vec<String>,
+    /// File name of converted journals, `YYYY`, `MM` and `DD` are replaced by
+    /// the date of the journal.
+    #[clap(long, default_value = "YYYY-MM-DD")]
+    journal_format: String,
+    /// Merge all journals into the page with this title instead, each under a
+    /// `## YYYY-MM-DD` heading in chronological order.
+    #[clap(long)]
+    flatten_journals_into: Option<String>,
+    /// Merge all pages whose title starts with this prefix into a single page
+    /// named after the prefix, each under a `## <rest of the title>` heading.
+    #[clap(long)]
+    merge_namespace: Option<String>,
+    /// Convert all pages and journals into this single file of the vault, each
+    /// under a `# <title>` heading, links between them point to the headings.
+    #[clap(long, value_name = "FILE")]
+    export_single: Option<PathBuf>,
+    /// Remove `SCHEDULED`/`DEADLINE` dates instead of converting them to Obsidian Tasks dates.
+    #[clap(long)]
+    no_task_dates: bool,
+    /// Strike through the text of done and canceled tasks.
+    #[clap(long)]
+    strike_done: bool,
+    /// Convert `DOING`/`NOW` tasks to `[/]` and `WAITING` tasks to `[?]` checkboxes.
+    #[clap(long)]
+    rich_task_states: bool,
+    /// Keep the logseq ids of referenced blocks as `%% logseq-id: ... %%` comments.
+    #[clap(long)]
+    keep_logseq_ids: bool,
+    /// Replace standalone block references with the text of the block instead of
+    /// linking to it. The texts are stored in the ids file as well.
+    #[clap(long)]
+    inline_refs: bool,
+    /// Write namespaced pages (`A/B/C`) into nested folders of the vault.
+    #[clap(long)]
+    namespaces: bool,
+    /// How blocks tagged with `#.v-self-border` are rendered (`admonition` or `native`).
+    #[clap(long, default_value = "admonition")]
+    callout_style: CalloutStyle,
+    /// How `{{cloze ...}}`s are converted (`highlight` for `==answer==` or `braces` for `{{answer}}`).
+    #[clap(long, default_value = "highlight")]
+    cloze_style: ClozeStyle,
+    /// How block properties are converted (`dataview` to keep them as Dataview
+    /// fields or `drop` to remove them).
+    #[clap(long, default_value = "dataview")]
+    block_props: BlockProps,
+    /// How the anchors of referenced blocks are named (`hash` or `slug` for the
+    /// first words of the block).
+    #[clap(long, default_value = "hash")]
+    anchor_style: AnchorStyle,
+    /// Remove `{{query ...}}`s instead of commenting them out.
+    #[clap(long)]
+    drop_queries: bool,
+    /// Remove `{{renderer ...}}`s instead of commenting them out.
+    #[clap(long)]
+    drop_renderers: bool,
+    /// Remove macros whose content logseq generates (`{{namespace}}`, `{{function}}`, ...)
+    /// instead of keeping them as comments.
+    #[clap(long)]
+    drop_dynamic: bool,
+    /// End the lines of multi-line paragraph blocks with a hard line break.
+    #[clap(long)]
+    hard_breaks: bool,
+    /// Keep blocks starting with bold text or a heading as list items.
+    #[clap(long)]
+    keep_bold_headers_as_list: bool,
+    /// Convert images to `![[path]]` embeds instead of markdown images.
+    #[clap(long)]
+    image_embeds: bool,
+    /// Keep image captions as an italic line beneath the image instead of its alt text.
+    #[clap(long)]
+    captions_below: bool,
+    /// Take an italic line directly below an image as its caption.
+    #[clap(long)]
+    italic_captions: bool,
+    /// Convert `#[[tag]]`s to `[[tag]]` page links instead of `#tag`s.
+    #[clap(long)]
+    tags_as_links: bool,
+    /// Replace blocks without text by their children instead of keeping an empty bullet.
+    #[clap(long)]
+    flatten_empty_groups: bool,
+    /// Convert highlights with a `[[$color]]` to `<mark>` elements of that color.
+    #[clap(long)]
+    html_highlights: bool,
+    /// Add `publish: true` to the frontmatter of `public:: true` pages.
+    #[clap(long)]
+    public_as_publish: bool,
+    /// The deepest level of nesting of the converted lists, deeper blocks are
+    /// moved up to it.
+    #[clap(long, default_value_t = 12)]
+    max_depth: usize,
+    /// Add the `#tag`s of the blocks to the tags of their page, removing those
+    /// at the end of a block.
+    #[clap(long)]
+    hoist_block_tags: bool,
+    /// Start the converted pages with their title as a `# heading`.
+    #[clap(long)]
+    title_heading: bool,
+    /// Remove the whitespace at the end of the lines of the converted pages.
+    #[clap(long)]
+    trim_trailing_whitespace: bool,
+    /// The file the extracted ids are stored in, defaults to `ids.json` next to the vault.
+    #[clap(long)]
+    ids_file: Option<PathBuf>,
+    /// Fail if any block reference can't be resolved or a block id is used by
+    /// several pages.
+    #[clap(long)]
+    strict: bool,
+    /// Copy all assets into this folder of the vault instead of next to the pages.
+    #[clap(long)]
+    attachments_dir: Option<PathBuf>,
+    /// Keep the folders below `assets` in the `--attachments-dir`.
+    #[clap(long)]
+    preserve_asset_tree: bool,
+    /// The `assets` folder of the graph, assets that can't be found by their
+    /// path are looked up in it by their file name.
+    #[clap(long)]
+    assets_dir: Option<PathBuf>,
+    /// The vaults of the pages whose title starts with a prefix, by prefix. Only
+    /// set by the config file.
+    #[clap(skip)]
+    routes: BTreeMap<String, PathBuf>,
+    /// `(pattern, replacement)` regex rules applied to the text of every block.
+    /// Only set by the config file.
+    #[clap(skip)]
+    replacements: Vec<(String, String)>,
+    /// Symlink assets into the vault instead of copying them.
+    #[clap(long)]
+    link_assets: bool,
+    /// Print the files that would be written instead of writing them.
+    #[clap(long)]
+    dry_run: bool,
+    /// Only check all pages for unresolved block references, missing assets,
+    /// untitled pages and file name collisions, and fail if there are any.
+    /// Nothing is written.
+    #[clap(long)]
+    check: bool,
+    /// Number of threads pages are processed on, the output only stays
+    /// deterministic with a single thread.
+    #[clap(long, default_value_t = 1)]
+    jobs: usize,
+    /// Don't report the progress of directory conversions.
+    #[clap(long)]
+    quiet: bool,
+    /// Print the parsed pages, their assets and every conversion pass that
+    /// changed a block to stderr.
+    #[clap(short, long)]
+    verbose: bool,
+    /// Convert all pages, even those that didn't change since the last run.
+    #[clap(long)]
+    force: bool,
+    /// Print a single converted page to stdout instead of writing it, its assets
+    /// are not copied.
+    #[clap(long)]
+    stdout: bool,
+    /// Title of a single converted page, instead of its `title::` or file name.
+    #[clap(long)]
+    title: Option<String>,
+    /// The file extension of the converted pages.
+    #[clap(long, default_value = "md")]
+    out_extension: String,
+    /// Also write the summary of the run as JSON to this file.
+    #[clap(long)]
+    report_json: Option<PathBuf>,
+    /// Write the obsidian link of every block id as JSON to this file.
+    #[clap(long)]
+    emit_mapping: Option<PathBuf>,
+    /// Read the options from this JSON file (e.g. `logseq2obsdn.json`), options
+    /// passed on the command line take precedence. The config is JSON, not TOML,
+    /// and is only read when passed, a file in the current directory isn't
+    /// picked up.
+    #[clap(long)]
+    config: Option<PathBuf>,
+}
+
+impl Args {
+    /// Parses the command line `args` and applies the config file, if any.
+    fn parse_with_config<I, T>(args: I) -> Result<Args>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<std::ffi::OsString> + Clone,
+    {
+        let args = args.into_iter().map(Into::into).collect_vec();
+        let matches = match Args::command().try_get_matches_from(&args) {
+            Ok(matches) => matches,
+            Err(e) if e.kind() == clap::ErrorKind::MissingRequiredArgument => {
+                return Err(missing_paths(&args));
+            }
+            Err(e) => e.exit(),
+        };
+        let mut args = Args::from_arg_matches(&matches)?;
+        if let Some(path) = &args.config {
+            config::Config::load(path)?.apply(&mut args, &matches);
+        }
+        Ok(args)
+    }
+}
+
+/// Explains the paths the command line `args` are missing.
+fn missing_paths(args: &[std::ffi::OsString]) -> anyhow::Error {
+    let input = Args::command()
+        .mut_arg("file-or-folder", |a| a.required(false))
+        .mut_arg("vault", |a| a.required(false))
+        .try_get_matches_from(args)
+        .ok()
+        .and_then(|m| Some(PathBuf::from(m.get_raw("file-or-folder")?.next()?)));
+    let usage =
+        "Usage: logseq2obsdn [OPTIONS] <LOGSEQ_DIR_OR_PAGE> <VAULT>, see --help for examples.";
+    let Some(input) = input else {
+        return anyhow!(
+            "Missing the logseq pages to convert and the obsidian vault to write them to.\n{usage}"
+        );
+    };
+
+    let hint = if !input.exists() {
+        format!("'{}' does not exist either.", input.display())
+    } else if input.is_dir() {
+        "Add --convert-all to resolve the block references between the pages in the same run."
+            .to_owned()
+    } else {
+        "Block references to other pages are only resolved after running --extract-ids on the pages directory."
+            .to_owned()
+    };
+    anyhow!(
+        "Missing the obsidian vault to write '{}' to, pass it after the logseq pages.\n{hint}\n{usage}",
+        input.display()
+    )
+}
+
+/// A logseq file to convert.
+#[derive(Clone)]
+struct Source {
+    path: PathBuf,
+    /// Overrides the title of the page.
+    title: Option<String>,
+    /// The logseq file the output page belongs to if it isn't `path`, used by
+    /// journals merged into a single page.
+    merged_into: Option<PathBuf>,
 }
 
+#[derive(Clone)]
 struct Data {
+    /// The logseq page that is processed.
+    page_path: PathBuf,
     page_dir: PathBuf,
     out_vault: PathBuf,
+    /// The vaults of the pages whose title starts with a prefix, by prefix.
+    routes: BTreeMap<String, PathBuf>,
     out_file: Option<(PathBuf, String)>,
+    out_extension: String,
+    title_override: Option<String>,
+    /// Assets of the current page as `(source, destination)`.
     files: Vec<(PathBuf, PathBuf)>,
+    /// The source of every asset destination handed out so far, shared by all threads.
+    asset_dests: Arc<Mutex<HashMap<PathBuf, PathBuf>>>,
+    /// The logseq page of every output file handed out so far, shared by all threads.
+    page_dests: Arc<Mutex<HashMap<PathBuf, PathBuf>>>,
+    /// Pages that were renamed because another page has the same file name, as
+    /// `(logseq page, output file)`.
+    collisions: BTreeSet<(PathBuf, PathBuf)>,
+    link_assets: bool,
+    dry_run: bool,
+    check: bool,
+    stdout: bool,
+    quiet: bool,
+    verbose: bool,
+    /// The pages converted by previous runs.
+    manifest: Arc<Manifest>,
+    /// The pages converted by this run.
+    converted: Vec<(PathBuf, manifest::Entry)>,
     refs_file: RefsFile,
+    config: page::Config,
+    /// The `--journal-format` of the converted journals, if any.
+    journal_format: Option<String>,
+    /// The page the journals are merged into.
+    journals_page: Option<String>,
+    /// The dates of the journals, links to other dates aren't journal links.
+    journal_dates: BTreeSet<(u32, u32, u32)>,
+    /// The titles of the pages of `--export-single` by their lowercase title.
+    exported_pages: BTreeMap<String, String>,
+    /// The logseq ids of the blocks of the current page.
+    page_ids: Vec<String>,
+    /// The logseq ids referenced by the current page.
+    page_refs: Vec<String>,
+    /// A hash of the options that change the converted pages.
+    options: u64,
+    /// The logseq ids registered by this run and the page that registered them first.
+    id_sources: BTreeMap<String, PathBuf>,
+    /// The logseq ids registered by several pages.
+    duplicate_ids: BTreeSet<String>,
+    /// The logseq ids referenced by the pages whose ids were extracted.
+    referenced: BTreeSet<String>,
+    /// Whether only the anchors of the `referenced` blocks are written.
+    only_referenced_anchors: bool,
+    /// Unresolved block references as `(page title, logseq id)`.
+    unresolved: BTreeSet<(String, String)>,
+    /// The warnings reported so far.
+    warnings: BTreeSet<String>,
+    /// The number of converted and unresolved block references.
+    resolved_refs: usize,
+    unresolved_refs: usize,
+    /// The number of pages skipped because they didn't change.
+    unchanged_pages: usize,
+    /// The number of pages a dry run would write.
+    dry_run_pages: usize,
+}
+
+impl Data {
+    /// Registers the ref `r` of the block `logseq_id` of the page `source`.
+    ///
+    /// The first page that registers an id keeps it, later pages with the same
+    /// id are reported instead.
+    fn insert_ref(&mut self, logseq_id: &str, r: Ref, text: Option<String>, source: PathBuf) {
+        if let Some(first) = self.id_sources.get(logseq_id) {
+            if *first != source && self.refs_file.refs.get(logseq_id) != Some(&r) {
+                let message = format!(
+                    "Block id {logseq_id} is used by both '{}' and '{}', references link to the former",
+                    first.display(),
+                    source.display()
+                );
+                self.duplicate_ids.insert(logseq_id.to_owned());
+                page::Data::warn(self, &message);
+            }
+            return;
+        }
+        self.id_sources.insert(logseq_id.to_owned(), source);
+        self.refs_file.refs.insert(logseq_id.to_owned(), r);
+        if let Some(text) = text {
+            self.refs_file.texts.insert(logseq_id.to_owned(), text);
+        }
+    }
 }
 
 impl page::Data for Data {
-    fn copy_asset(&mut self, path: &str) -> String {
-        let src = self.page_dir.join(path).canonicalize().unwrap();
-        let dest: PathBuf = Path::new(path).components().skip(1).collect();
+    fn copy_asset(&mut self, path: &str) -> Result<String> {
+        let url = path.strip_prefix("file://").map(page::percent_decode);
+        let path = url.as_deref().unwrap_or(path);
+        // Logseq on macOS and Windows doesn't care about the case of the path.
+        let matched = match self.page_dir.join(path).exists() {
+            true => None,
+            false => match_case(&self.page_dir, Path::new(path)),
+        };
+        let matched = matched.as_ref().map(|p| p.to_string_lossy());
+        let path = matched.as_deref().unwrap_or(path);
+        // Joining keeps absolute paths as they are.
+        let joined = self.page_dir.join(path);
+        let (src, found_by_name) = joined
+            .canonicalize()
+            .map(|p| (p, false))
+            .or_else(
+                |e| match (&self.config.assets_dir, Path::new(path).file_name()) {
+                    (Some(dir), Some(name)) => dir.join(name).canonicalize().map(|p| (p, true)),
+                    _ => Err(e),
+                },
+            )
+            .with_context(|| anyhow!("Could not find '{}'", joined.display()))?;
+        let src = strip_verbatim_prefix(&src);
+
+        // Names are composed, so that they are the same on every system.
+        let path = nfc::to_nfc(path);
+        let path = path.as_str();
+        let (dest, link) = match self.config.attachments_dir.clone() {
+            Some(dir) => {
+                let file_name = Path::new(path)
+                    .file_name()
+                    .ok_or_else(|| anyhow!("'{path}' is not a file"))?;
+                let outside = found_by_name || Path::new(path).is_absolute();
+                let relative = match self.config.preserve_asset_tree && !outside {
+                    true => {
+                        let link = asset_link(Path::new(path));
+                        match link.strip_prefix("assets") {
+                            Ok(relative) => relative.to_owned(),
+                            Err(_) => link,
+                        }
+                    }
+                    false => PathBuf::from(file_name),
+                };
+                let vault = self.vault_of(self.curr_title()).to_owned();
+                let dest = self.claim_asset_dest(&src, vault.join(&dir).join(&relative));
+                let link = dir.join(relative.with_file_name(dest.file_name().unwrap()));
+                (dest, link)
+            }
+            None => {
+                // Assets outside of the graph go directly into `assets`.
+                let link = match found_by_name || Path::new(path).is_absolute() {
+                    true => Path::new("assets").join(
+                        Path::new(path)
+                            .file_name()
+                            .ok_or_else(|| anyhow!("'{path}' is not a file"))?,
+                    ),
+                    false => asset_link(Path::new(path)),
+                };
+                let dest = self.claim_asset_dest(&src, self.out_dir().join(&link));
+                let link = link.with_file_name(dest.file_name().unwrap());
+                (dest, link)
+            }
+        };
 
-        let result = dest.display().to_string().replace("\\", "/");
+        let result = link.display().to_string().replace("\\", "/");
 
         self.files.push((src, dest));
-        result
+        Ok(result)
+    }
+
+    fn asset_plan(&self) -> &[(PathBuf, PathBuf)] {
+        &self.files
     }
 
     fn page_title(&mut self, title: &str) {
-        let title = title.trim();
-        let vault_relative_path = title.to_owned();
-        self.out_file = Some((
-            self.out_vault.join(&format!("{vault_relative_path}.md")),
-            vault_relative_path,
-        ));
+        let mut title = nfc::to_nfc(self.title_override.as_deref().unwrap_or(title).trim());
+        let file_name = match self.config.namespaces {
+            true => title.rsplit('/').next().unwrap_or_default(),
+            false => &title,
+        };
+        // A title without a single valid character would result in `.md`.
+        if sanitize_file_name(file_name.trim()).is_empty() {
+            let stem = self.page_path.file_stem().unwrap_or_default();
+            title = match sanitize_file_name(&page::decode_title(&stem.to_string_lossy())) {
+                stem if stem.is_empty() => {
+                    let message = format!("'{}' has no title", self.page_path.display());
+                    page::Data::warn(self, &message);
+                    "Untitled".to_owned()
+                }
+                stem => stem,
+            };
+        }
+        let title = title.as_str();
+        let out_path = if self.config.namespaces {
+            let mut path: PathBuf = title
+                .split('/')
+                .map(|c| sanitize_file_name(c.trim()))
+                .collect();
+            path.set_file_name(format!(
+                "{}.{}",
+                path.file_name().unwrap().to_string_lossy(),
+                self.out_extension
+            ));
+            self.vault_of(title).join(path)
+        } else {
+            self.vault_of(title).join(format!(
+                "{}.{}",
+                sanitize_file_name(title),
+                self.out_extension
+            ))
+        };
+
+        let (out_path, suffix) = claim_path(&self.page_dests, &self.page_path, out_path);
+        // Links keep using the title, only the file name is sanitized.
+        let title = if suffix > 0 {
+            self.collisions
+                .insert((self.page_path.clone(), out_path.clone()));
+            format!("{title} {suffix}")
+        } else {
+            title.to_owned()
+        };
+        self.out_file = Some((out_path, title));
     }
 
     fn register_id(&mut self, id: &page::Id) {
+        if !id.obsdn_id.is_empty() {
+            self.page_ids.push(id.logseq_id.clone());
+        }
         let obsdn_file = &self.out_file.as_ref().unwrap().1;
         if obsdn_file.is_empty() {
             return;
@@ -53,15 +538,16 @@ impl page::Data for Data {
 
         let obsdn_id = &id.obsdn_id;
 
+        #[allow(clippy::obfuscated_if_else)]
         let hash = obsdn_id.starts_with('^').then_some("#").unwrap_or_default();
 
-        self.refs_file.refs.insert(
-            id.logseq_id.clone(),
-            Ref {
-                file: obsdn_file.to_owned(),
-                id: format!("{hash}{obsdn_id}"),
-            },
-        );
+        let r = Ref {
+            file: obsdn_file.to_owned(),
+            id: format!("{hash}{obsdn_id}"),
+        };
+        let text = self.config.inline_refs.then(|| id.text.clone());
+        let source = self.page_path.clone();
+        self.insert_ref(&id.logseq_id, r, text, source);
     }
 
     fn query_id(&self, logseq_id: &str) -> Option<&Ref> {
@@ -73,60 +559,2720 @@ impl page::Data for Data {
             .map(|(_, n)| n.as_str())
             .unwrap_or_default()
     }
+
+    fn query_block_text(&self, logseq_id: &str) -> Option<&str> {
+        self.refs_file.texts.get(logseq_id).map(String::as_str)
+    }
+
+    fn tracing(&self) -> bool {
+        self.verbose
+    }
+
+    fn trace(&mut self, pass: &str, before: &str, after: &str) {
+        eprintln!("[{}] {pass}: {before:?} -> {after:?}", self.curr_title());
+    }
+
+    fn journal_link(&self, page: &str) -> Option<String> {
+        let format = self.journal_format.as_deref()?;
+        let date = journal::parse_journal_date(page).filter(|d| self.journal_dates.contains(d))?;
+        Some(match &self.journals_page {
+            Some(title) => format!("{title}#{}", journal::format_date("YYYY-MM-DD", date)),
+            None => journal::format_date(format, date),
+        })
+    }
+
+    fn page_link(&self, page: &str) -> Option<String> {
+        let title = self.exported_pages.get(&page.trim().to_lowercase())?;
+        Some(format!("#{title}"))
+    }
+
+    fn register_alias(&mut self, alias: &str) {
+        let title = &self.out_file.as_ref().unwrap().1;
+        if !title.is_empty() {
+            let alias = alias.trim().to_lowercase();
+            self.refs_file.aliases.insert(alias, title.clone());
+        }
+    }
+
+    fn query_alias(&self, alias: &str) -> Option<&str> {
+        let alias = alias.trim().to_lowercase();
+        self.refs_file.aliases.get(&alias).map(String::as_str)
+    }
+
+    fn resolved_ref(&mut self, logseq_id: &str) {
+        self.resolved_refs += 1;
+        let Some(file) = self.refs_file.refs.get(logseq_id).map(|r| r.file.clone()) else {
+            return;
+        };
+        let curr_title = self.curr_title().to_owned();
+        if self.vault_of(&file) != self.vault_of(&curr_title) {
+            let message = format!("'{curr_title}' links to '{file}' in another vault");
+            page::Data::warn(self, &message);
+        }
+    }
+
+    fn referenced_id(&mut self, logseq_id: &str) {
+        self.referenced.insert(logseq_id.to_owned());
+        self.page_refs.push(logseq_id.to_owned());
+    }
+
+    fn is_referenced(&self, logseq_id: &str) -> bool {
+        !self.only_referenced_anchors || self.referenced.contains(logseq_id)
+    }
+
+    fn warn(&mut self, message: &str) {
+        // Pages are parsed twice by some runs.
+        if self.warnings.insert(message.to_owned()) {
+            eprintln!("Warning: {message}");
+        }
+    }
+
+    fn unresolved_ref(&mut self, logseq_id: &str) {
+        let title = self.curr_title().to_owned();
+        self.unresolved.insert((title, logseq_id.to_owned()));
+        self.unresolved_refs += 1;
+    }
+
+    fn config(&self) -> &page::Config {
+        &self.config
+    }
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+impl Data {
+    /// The vault the page `title` is written to, chosen by the longest route
+    /// prefix of it.
+    fn vault_of(&self, title: &str) -> &Path {
+        self.routes
+            .iter()
+            .filter(|(prefix, _)| title.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(&self.out_vault, |(_, vault)| vault)
+    }
 
-    let ids_file = "ids.json";
-    let refs_file = std::fs::File::open(ids_file)
-        .map_err(anyhow::Error::from)
-        .and_then(|f| Ok(serde_json::from_reader::<_, RefsFile>(BufReader::new(f))?))
+    /// The ids of `ids` whose anchor is written.
+    fn anchors(&self, ids: &[String]) -> Vec<String> {
+        ids.iter()
+            .filter(|id| page::Data::is_referenced(self, id))
+            .cloned()
+            .collect()
+    }
+
+    /// A hash of what the references `ids`, all aliases and journal dates
+    /// resolve to.
+    fn refs_hash(&self, ids: &[String]) -> u64 {
+        let refs = ids
+            .iter()
+            .map(|id| (self.refs_file.refs.get(id), self.refs_file.texts.get(id)))
+            .collect_vec();
+        let json =
+            serde_json::to_string(&(refs, &self.refs_file.aliases, &self.journal_dates)).unwrap();
+        page::fnv1a(json.as_bytes())
+    }
+
+    /// A hash of the options that change the converted pages.
+    fn options_hash(&self) -> u64 {
+        let options = format!(
+            "{:?}",
+            (
+                &self.config,
+                &self.routes,
+                &self.out_extension,
+                self.link_assets,
+                &self.journal_format,
+                &self.journals_page,
+            )
+        );
+        page::fnv1a(options.as_bytes())
+    }
+
+    /// The manifest entry of the current page, converted from a page modified
+    /// at `modified` and whose assets are `assets`.
+    fn manifest_entry(
+        &self,
+        modified: SystemTime,
+        assets: Vec<(PathBuf, PathBuf)>,
+    ) -> manifest::Entry {
+        manifest::Entry {
+            modified,
+            output: self.out_file.as_ref().unwrap().0.clone(),
+            assets,
+            ids: self.page_ids.clone(),
+            anchors: self.anchors(&self.page_ids),
+            options: self.options,
+            refs: self.page_refs.clone(),
+            refs_hash: self.refs_hash(&self.page_refs),
+        }
+    }
+
+    /// The folder the current page is written to.
+    fn out_dir(&self) -> &Path {
+        self.out_file.as_ref().unwrap().0.parent().unwrap()
+    }
+
+    /// Returns `dest`, or `dest` with a numeric suffix if a different asset was
+    /// already copied there.
+    fn claim_asset_dest(&mut self, src: &Path, dest: PathBuf) -> PathBuf {
+        claim_path(&self.asset_dests, src, dest).0
+    }
+}
+
+/// Claims `dest` for `src` in `claims` and returns it, or if a different source
+/// already claimed it, the first free path with a numeric suffix and the suffix.
+///
+/// Paths are compared ignoring case, as not all file systems are case-sensitive.
+fn claim_path(
+    claims: &Mutex<HashMap<PathBuf, PathBuf>>,
+    src: &Path,
+    dest: PathBuf,
+) -> (PathBuf, usize) {
+    let stem = dest
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    let ext = dest
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
         .unwrap_or_default();
 
-    let mut data = Data {
-        page_dir: Path::new(&args.file_or_folder).parent().unwrap().to_owned(),
-        out_vault: args.vault.clone(),
-        out_file: None,
-        files: vec![],
-        refs_file,
+    let mut claims = claims.lock().unwrap();
+    let mut candidate = dest.clone();
+    let mut suffix = 0;
+    loop {
+        let key = PathBuf::from(candidate.to_string_lossy().to_lowercase());
+        match claims.get(&key) {
+            Some(s) if s != src => {
+                suffix += 1;
+                candidate = dest.with_file_name(format!("{stem} {suffix}{ext}"));
+            }
+            Some(_) => break,
+            None => {
+                claims.insert(key, src.to_owned());
+                break;
+            }
+        }
+    }
+    (candidate, suffix)
+}
+
+#[cfg(unix)]
+fn symlink_file(src: &Path, dest: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(src, dest)
+}
+
+#[cfg(windows)]
+fn symlink_file(src: &Path, dest: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(src, dest)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink_file(_src: &Path, _dest: &Path) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+/// Replaces the characters that are not allowed in file names on some systems
+/// with `_` and removes trailing dots and spaces.
+fn sanitize_file_name(name: &str) -> String {
+    let name = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect::<String>();
+    name.trim_end_matches(['.', ' ']).to_owned()
+}
+
+/// Returns the part of the relative asset `path` starting at its `assets`
+/// directory, `../../assets/x.png` becomes `assets/x.png`.
+///
+/// Paths without an `assets` directory only lose their leading `..` and `.`.
+fn asset_link(path: &Path) -> PathBuf {
+    let components = path.components().collect::<Vec<_>>();
+    let start = components
+        .iter()
+        .position(|c| c.as_os_str() == "assets")
+        .unwrap_or_else(|| {
+            components
+                .iter()
+                .take_while(|c| matches!(c, Component::ParentDir | Component::CurDir))
+                .count()
+        });
+    components[start..].iter().collect()
+}
+
+#[test]
+fn test_asset_link() {
+    let link = |p: &str| asset_link(Path::new(p));
+    assert_eq!(link("../assets/x.png"), Path::new("assets/x.png"));
+    assert_eq!(link("../../assets/x.png"), Path::new("assets/x.png"));
+    assert_eq!(link("assets/x.png"), Path::new("assets/x.png"));
+    assert_eq!(link("../assets/sub/x.png"), Path::new("assets/sub/x.png"));
+    assert_eq!(link("../images/x.png"), Path::new("images/x.png"));
+}
+
+/// Returns `path` relative to `dir` with the case of the existing files, if
+/// they only differ in case.
+fn match_case(dir: &Path, path: &Path) -> Option<PathBuf> {
+    let mut matched = PathBuf::new();
+    for c in path.components() {
+        let Component::Normal(name) = c else {
+            matched.push(c);
+            continue;
+        };
+        let parent = dir.join(&matched);
+        if !parent.join(name).exists() {
+            let name = name.to_string_lossy().to_lowercase();
+            let entry = std::fs::read_dir(&parent)
+                .ok()?
+                .filter_map(|e| e.ok())
+                .find(|e| e.file_name().to_string_lossy().to_lowercase() == name)?;
+            matched.push(entry.file_name());
+        } else {
+            matched.push(name);
+        }
+    }
+    Some(matched)
+}
+
+/// Removes the `\\?\` extended-length prefix that `canonicalize` adds on Windows,
+/// `\\?\UNC\server\share` becomes `\\server\share`.
+fn strip_verbatim_prefix(path: &Path) -> PathBuf {
+    let s = path.to_string_lossy();
+    match s.strip_prefix(r"\\?\") {
+        Some(p) => match p.strip_prefix(r"UNC\") {
+            Some(share) => PathBuf::from(format!(r"\\{share}")),
+            None => PathBuf::from(p),
+        },
+        None => path.to_owned(),
+    }
+}
+
+#[test]
+fn test_strip_verbatim_prefix() {
+    let strip = |p: &str| strip_verbatim_prefix(Path::new(p)).display().to_string();
+    assert_eq!(strip(r"\\?\E:\notes\a.png"), r"E:\notes\a.png");
+    assert_eq!(
+        strip(r"\\?\UNC\server\share\a.png"),
+        r"\\server\share\a.png"
+    );
+    assert_eq!(strip("/notes/a.png"), "/notes/a.png");
+}
+
+#[cfg(windows)]
+#[test]
+fn test_canonicalized_asset_path() {
+    let dir = std::env::temp_dir().canonicalize().unwrap();
+    assert!(dir.to_string_lossy().starts_with(r"\\?\"));
+    assert!(!strip_verbatim_prefix(&dir)
+        .to_string_lossy()
+        .starts_with(r"\\?\"));
+}
+
+/// Parses, transforms and writes a single logseq page, then copies its assets
+/// next to the converted file.
+fn convert_file(source: &Source, data: &mut Data) -> Result<()> {
+    let path = source.path.as_path();
+    data.page_path = path.to_owned();
+    data.page_dir = path.parent().unwrap().to_owned();
+    data.out_file = None;
+    data.title_override = source.title.clone();
+    data.files.clear();
+    data.page_ids.clear();
+    data.page_refs.clear();
+
+    let modified = std::fs::metadata(path)?.modified()?;
+    let mut page = page::Page::parse_file(path, data)?;
+    page.transform(data);
+
+    if data.verbose {
+        eprintln!("{:#?}", page);
+        eprintln!("{:#?}", page::Data::asset_plan(data));
+    }
+
+    let content = page.to_string(data);
+    if let Some(assets) = write_page(data, &content)? {
+        let entry = data.manifest_entry(modified, assets);
+        data.converted.push((path.to_owned(), entry));
+    }
+    Ok(())
+}
+
+/// Converts all `sources` into the single page `title`, in the order they are
+/// given, each under its `heading`, which is given the path and logseq title of
+/// the source.
+fn convert_merged(
+    sources: &[Source],
+    title: &str,
+    heading: impl Fn(&Path, &str) -> String,
+    data: &mut Data,
+) -> Result<()> {
+    let dir = match sources.first().and_then(|j| j.merged_into.clone()) {
+        Some(dir) => dir,
+        None => return Ok(()),
     };
+    data.page_path = dir.clone();
+    data.out_file = None;
+    data.title_override = Some(title.to_owned());
+    data.files.clear();
+    data.page_ids.clear();
+    data.page_refs.clear();
+
+    let mut modified = SystemTime::UNIX_EPOCH;
+    let mut sections = vec![];
+    // The anchors must be unique in the merged page, like in `extract_ids`.
+    let mut anchors = HashSet::new();
+    for source in sources {
+        let path = source.path.as_path();
+        data.page_dir = path.parent().unwrap().to_owned();
+        let context = || anyhow!("Could not convert '{}'", path.display());
+        modified = modified.max(
+            std::fs::metadata(path)
+                .and_then(|m| m.modified())
+                .with_context(context)?,
+        );
+        let mut page =
+            page::Page::parse_file_with_anchors(path, data, &mut anchors).with_context(context)?;
+        page.transform(data);
+        let heading = heading(path, &page.title);
+        page.demote_headings(heading.chars().take_while(|&c| c == '#').count());
+        // The properties of the merged pages don't belong to the merged page.
+        page.alias.clear();
+        page.tags.clear();
+        page.props.clear();
+        page.frontmatter.clear();
+        sections.push(format!("{heading}\n\n{}", page.to_string(data)));
+    }
+
+    if let Some(assets) = write_page(data, &sections.join("\n"))? {
+        let entry = data.manifest_entry(modified, assets);
+        data.converted.push((dir, entry));
+    }
+    Ok(())
+}
+
+/// Writes `content` to the output file of the current page and copies its
+/// assets, returns the assets as `(source, destination)` unless nothing is
+/// written.
+fn write_page(data: &mut Data, content: &str) -> Result<Option<Vec<(PathBuf, PathBuf)>>> {
+    let out_file_path = data.out_file.as_ref().unwrap().0.clone();
+    if data.check {
+        data.files.clear();
+        return Ok(None);
+    }
+    if data.dry_run {
+        data.dry_run_pages += 1;
+        println!(
+            "Would write page '{}' ({} bytes)",
+            out_file_path.display(),
+            content.len()
+        );
+        for (src, dest) in data.files.drain(..) {
+            println!(
+                "Would copy asset '{}' to '{}'",
+                src.display(),
+                dest.display()
+            );
+        }
+        return Ok(None);
+    }
+    if data.stdout {
+        print!("{content}");
+        for (src, _) in data.files.drain(..) {
+            eprintln!("Not copying asset '{}'", src.display());
+        }
+        return Ok(None);
+    }
+
+    let out_dir = out_file_path.parent().unwrap();
+    if data.config.namespaces {
+        std::fs::create_dir_all(out_dir)?;
+    }
+
+    let mut file = std::fs::File::create(&out_file_path)?;
+    file.write_all(content.as_bytes())?;
+
+    let files = std::mem::take(&mut data.files);
+    let mut copies = vec![];
+    for (src, dest) in &files {
+        let context = || anyhow!("Could not copy '{}' to '{}'", src.display(), dest.display());
+
+        std::fs::create_dir_all(dest.parent().unwrap()).with_context(context)?;
+        // Never copy through a symlink of a previous run, it would overwrite the source.
+        if dest.is_symlink() || (data.link_assets && dest.exists()) {
+            std::fs::remove_file(dest).with_context(context)?;
+        }
+
+        if data.link_assets {
+            match symlink_file(src, dest) {
+                Ok(()) => continue,
+                Err(e) => page::Data::warn(
+                    data,
+                    &format!(
+                        "Could not link '{}' to '{}', copying it instead: {e}",
+                        src.display(),
+                        dest.display()
+                    ),
+                ),
+            }
+        }
+        copies.push((src.clone(), dest.clone()));
+    }
+    // The destinations already contain the vault.
+    logseq2obsdn::assets::execute_asset_copies(&copies, Path::new(""))?;
+    Ok(Some(files))
+}
 
-    if args.extract_ids {
-        for f in std::fs::read_dir(&args.file_or_folder)
-            .with_context(|| anyhow!("Could not read dir '{}'", &args.file_or_folder.display()))?
+/// Returns all markdown and org-mode pages inside `dir` and its subdirectories
+/// that pass `filter`.
+///
+/// Symlinked directories are not followed.
+fn page_files(root: &Path, filter: &PathFilter) -> Result<Vec<Source>> {
+    let mut files = vec![];
+    let mut dirs = vec![root.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        for f in std::fs::read_dir(&dir)
+            .with_context(|| anyhow!("Could not read dir '{}'", dir.display()))?
         {
             let entry = f?;
-            if !entry.path().extension().map(|e| e == "md").unwrap_or(false) {
-                continue;
+            let path = entry.path();
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            if entry.file_type()?.is_dir() {
+                if !filter.excludes_dir(relative) {
+                    dirs.push(path);
+                }
+            } else if path
+                .extension()
+                .map(|e| e == "md" || e == "org")
+                .unwrap_or(false)
+                && filter.allows(relative)
+            {
+                files.push(Source {
+                    path,
+                    title: None,
+                    merged_into: None,
+                });
             }
-            eprintln!("Extracting ids from '{}'", entry.path().display());
-            let page_file = std::fs::read_to_string(entry.path())?;
-            page::Page::parse(&entry.path(), &page_file, &mut data)?;
         }
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(files)
+}
 
-        let ids_w = BufWriter::new(std::fs::File::create(ids_file)?);
-        serde_json::to_writer_pretty(ids_w, &data.refs_file)?;
-    } else {
-        let page_file = std::fs::read_to_string(&args.file_or_folder)?;
-        let mut page = page::Page::parse(&args.file_or_folder, &page_file, &mut data)?;
-        page.transform(&mut data);
+/// Returns all journals inside `dir`, titled by their date formatted with `format`.
+///
+/// Journals whose file name is not a date keep the title of the page.
+fn journal_files(dir: &Path, format: &str, filter: &PathFilter) -> Result<Vec<Source>> {
+    let mut files = page_files(dir, filter)?;
+    for source in &mut files {
+        let stem = source
+            .path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy();
+        source.title = journal::journal_title(&stem, format);
+    }
+    Ok(files)
+}
+
+/// Returns the ISO date of the journal `path`, or its file stem if that isn't a date.
+fn journal_date(path: &Path) -> String {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    journal::journal_title(&stem, "YYYY-MM-DD").unwrap_or_else(|| stem.into_owned())
+}
+
+/// Converts the `journals`, either each on its own or merged into one page.
+fn convert_journals(journals: &[Source], args: &Args, data: &mut Data) -> Vec<Failure> {
+    match &args.flatten_journals_into {
+        Some(title) => {
+            let heading = |path: &Path, _: &str| format!("## {}", journal_date(path));
+            match convert_merged(journals, title, heading, data) {
+                Ok(()) => vec![],
+                Err(e) => {
+                    eprintln!("{e:#}");
+                    vec![(args.journals.clone().unwrap_or_default(), e)]
+                }
+            }
+        }
+        None => convert_files(journals, data, args.jobs),
+    }
+}
 
-        println!("{:#?}", page);
-        println!("{:#?}", data.files);
+/// Returns the logseq title of the page `path`, like parsing it does.
+fn logseq_title(path: &Path) -> String {
+    let text = std::fs::read_to_string(path).unwrap_or_default();
+    page::page_title(path, &text).trim().to_owned()
+}
+
+/// Marks the `files` whose title starts with `prefix` as merged into the page
+/// `prefix` of `dir`, sorted by title.
+fn mark_merged(files: &mut [Source], prefix: &str, dir: &Path) {
+    let title = prefix.trim_end_matches('/').to_owned();
+    let titles: HashMap<_, _> = files
+        .iter()
+        .map(|s| (s.path.clone(), logseq_title(&s.path)))
+        .collect();
+    for source in files.iter_mut() {
+        if titles[&source.path].starts_with(prefix) {
+            source.title = Some(title.clone());
+            source.merged_into = Some(dir.to_owned());
+        }
+    }
+    files.sort_by_cached_key(|s| (s.merged_into.is_some(), titles[&s.path].clone()));
+}
 
-        let out_file_path = &data.out_file.as_ref().unwrap().0;
-        let out_dir = out_file_path.parent().unwrap();
+/// Converts the pages of a directory, those marked by [`mark_merged`] into
+/// their merged page.
+fn convert_pages(files: &[Source], args: &Args, data: &mut Data) -> Vec<Failure> {
+    let (merged, files): (Vec<_>, Vec<_>) =
+        files.iter().cloned().partition(|s| s.merged_into.is_some());
+    let mut failures = convert_files(&files, data, args.jobs);
+    if let Some(prefix) = &args.merge_namespace {
+        let title = prefix.trim_end_matches('/');
+        let heading = |_: &Path, title: &str| {
+            let title = title.trim();
+            format!("## {}", title.get(prefix.len()..).unwrap_or(title))
+        };
+        if let Err(e) = convert_merged(&merged, title, heading, data) {
+            eprintln!("{e:#}");
+            failures.push((args.file_or_folder.clone(), e));
+        }
+    }
+    failures
+}
 
-        let mut file = std::fs::File::create(&out_file_path)?;
-        file.write_all(page.to_string().as_bytes())?;
+/// The summary of a run.
+#[derive(serde::Serialize)]
+struct Report {
+    converted_pages: usize,
+    unchanged_pages: usize,
+    copied_assets: usize,
+    resolved_refs: usize,
+    unresolved_refs: usize,
+    failed_files: Vec<PathBuf>,
+}
 
-        for (src, dest) in data.files {
-            let dest = out_dir.join(dest);
-            std::fs::copy(&src, &dest)
-                .with_context(|| anyhow!("copy {} -> {}", src.display(), dest.display()))?;
+impl Report {
+    /// The line printed after converting, dry runs only tell what they would
+    /// have done.
+    fn summary(&self, dry_run: bool) -> String {
+        let refs = format!(
+            "resolved {} of {} block references, {} files failed",
+            self.resolved_refs,
+            self.resolved_refs + self.unresolved_refs,
+            self.failed_files.len()
+        );
+        match dry_run {
+            true => format!(
+                "Would convert {} pages ({} unchanged), {refs}",
+                self.converted_pages, self.unchanged_pages
+            ),
+            false => format!(
+                "Converted {} pages ({} unchanged), copied {} assets, {refs}",
+                self.converted_pages, self.unchanged_pages, self.copied_assets
+            ),
         }
     }
+}
 
-    Ok(())
+/// The obsidian link of a logseq id, as written by `--emit-mapping`.
+#[derive(serde::Serialize)]
+struct Mapping {
+    file: String,
+    /// The block anchor or heading, empty for pages.
+    anchor: String,
+    link: String,
+}
+
+impl Mapping {
+    fn new(r: &Ref) -> Mapping {
+        Mapping {
+            file: r.file.clone(),
+            anchor: r.id.trim_start_matches('#').to_owned(),
+            link: format!("[[{}]]", r.get_link("")),
+        }
+    }
+}
+
+/// A file that could not be processed.
+type Failure = (PathBuf, anyhow::Error);
+
+/// Reports how many of the files of a directory run are done.
+///
+/// Draws a single updating line on a terminal, and a line for every tenth of
+/// the files otherwise.
+struct Progress {
+    action: &'static str,
+    total: usize,
+    done: AtomicUsize,
+    is_terminal: bool,
+    quiet: bool,
+}
+
+impl Progress {
+    fn new(action: &'static str, total: usize, quiet: bool) -> Progress {
+        Progress {
+            action,
+            total,
+            done: AtomicUsize::new(0),
+            is_terminal: std::io::stderr().is_terminal(),
+            quiet,
+        }
+    }
+
+    /// Marks `path` as done.
+    fn step(&self, path: &Path) {
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        let (action, total) = (self.action, self.total);
+        if self.quiet {
+            return;
+        }
+
+        if self.is_terminal {
+            eprint!("\r\x1b[K{action} {done}/{total}: {}", path.display());
+            if done == total {
+                eprintln!();
+            }
+        } else if done == total || done.is_multiple_of((total / 10).max(1)) {
+            eprintln!("{action} {done}/{total} files");
+        }
+    }
+}
+
+/// Runs `f` on `jobs` threads, each with its own copy of `data` and a chunk of
+/// `files`, then merges the copies back into `data`.
+///
+/// Unless `share_refs`, the threads start without any refs, so merging the refs
+/// they registered gives the same result as processing `files` in order.
+fn in_parallel<F>(
+    files: &[Source],
+    data: &mut Data,
+    jobs: usize,
+    share_refs: bool,
+    f: F,
+) -> Vec<Failure>
+where
+    F: Fn(&[Source], &mut Data) -> Vec<Failure> + Sync,
+{
+    if jobs <= 1 || files.len() <= 1 {
+        return f(files, data);
+    }
+
+    let refs = (!share_refs).then(|| std::mem::take(&mut data.refs_file));
+    let f = &f;
+    let workers = std::thread::scope(|s| {
+        files
+            .chunks(files.len().div_ceil(jobs))
+            .map(|chunk| {
+                let mut worker = data.clone();
+                s.spawn(move || {
+                    let failures = f(chunk, &mut worker);
+                    (worker, failures)
+                })
+            })
+            .collect_vec()
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .collect_vec()
+    });
+    if let Some(refs) = refs {
+        data.refs_file = refs;
+    }
+
+    let mut failures = vec![];
+    for (mut worker, worker_failures) in workers {
+        // The workers are merged in order, so the first page of an id keeps it.
+        for (id, source) in std::mem::take(&mut worker.id_sources) {
+            let Some(r) = worker.refs_file.refs.remove(&id) else {
+                continue;
+            };
+            let text = worker.refs_file.texts.remove(&id);
+            data.insert_ref(&id, r, text, source);
+        }
+        data.refs_file.refs.extend(worker.refs_file.refs);
+        data.refs_file.texts.extend(worker.refs_file.texts);
+        data.refs_file.aliases.extend(worker.refs_file.aliases);
+        data.duplicate_ids.extend(worker.duplicate_ids);
+        data.referenced.extend(worker.referenced);
+        data.unresolved.extend(worker.unresolved);
+        data.collisions.extend(worker.collisions);
+        data.warnings.extend(worker.warnings);
+        data.resolved_refs += worker.resolved_refs;
+        data.unresolved_refs += worker.unresolved_refs;
+        data.dry_run_pages += worker.dry_run_pages;
+        data.converted.extend(worker.converted);
+        failures.extend(worker_failures);
+    }
+    failures
+}
+
+/// Registers the ids of all `files`, returns the files that failed to parse.
+fn extract_ids(files: &[Source], data: &mut Data, jobs: usize) -> Vec<Failure> {
+    let progress = Progress::new("Extracting ids", files.len(), data.quiet);
+    // The sources of a merged page share its anchors, so they are extracted
+    // in order by a single worker.
+    let (merged, files): (Vec<_>, Vec<_>) =
+        files.iter().cloned().partition(|s| s.merged_into.is_some());
+    let extract = |source: &Source, data: &mut Data, anchors: &mut HashSet<String>| {
+        let path = source.path.as_path();
+        progress.step(path);
+        data.page_path = source
+            .merged_into
+            .clone()
+            .unwrap_or_else(|| path.to_owned());
+        data.title_override = source.title.clone();
+        data.page_ids.clear();
+        match page::Page::parse_file_with_anchors(path, data, anchors) {
+            Err(e) if is_not_utf8(&e) => warn_not_utf8(path, data),
+            Err(e) => {
+                eprintln!("Could not extract ids from '{}': {e:#}", path.display());
+                return Some((path.to_owned(), e));
+            }
+            Ok(_) => (),
+        }
+        None
+    };
+    let mut failures = in_parallel(&files, data, jobs, false, |files, data| {
+        files
+            .iter()
+            .filter_map(|s| extract(s, data, &mut HashSet::new()))
+            .collect()
+    });
+    let mut anchors: HashMap<PathBuf, HashSet<String>> = HashMap::new();
+    for source in &merged {
+        let page_anchors = anchors
+            .entry(source.merged_into.clone().unwrap())
+            .or_default();
+        failures.extend(extract(source, data, page_anchors));
+    }
+    failures
+}
+
+/// Whether `e` is caused by reading a file that isn't UTF-8.
+fn is_not_utf8(e: &anyhow::Error) -> bool {
+    e.chain().any(|e| e.is::<std::string::FromUtf8Error>())
+}
+
+/// Directory runs skip pages that aren't UTF-8 instead of failing.
+fn warn_not_utf8(path: &Path, data: &mut Data) {
+    let message = format!("Skipping '{}', it isn't valid UTF-8", path.display());
+    page::Data::warn(data, &message);
+}
+
+/// Converts all `files` that changed since the last run, returns the files
+/// that failed to convert.
+fn convert_files(files: &[Source], data: &mut Data, jobs: usize) -> Vec<Failure> {
+    let manifest = Arc::clone(&data.manifest);
+    // Pages whose blocks were referenced or unreferenced since are converted
+    // again for their anchors, like those converted with other options or
+    // whose references changed.
+    let (unchanged, files): (Vec<_>, Vec<_>) = files.iter().partition(|f| {
+        manifest.unchanged(&f.path).is_some_and(|e| {
+            data.anchors(&e.ids) == e.anchors
+                && e.options == data.options
+                && data.refs_hash(&e.refs) == e.refs_hash
+        })
+    });
+    data.unchanged_pages += unchanged.len();
+    // Keep the file names of skipped pages, so that no other page takes them.
+    for source in unchanged {
+        let entry = &manifest.pages[&source.path];
+        claim_path(&data.page_dests, &source.path, entry.output.clone());
+        for (src, dest) in &entry.assets {
+            data.claim_asset_dest(src, dest.clone());
+        }
+    }
+    let files = files.into_iter().cloned().collect_vec();
+    let files = &files[..];
+
+    let progress = Progress::new("Converting", files.len(), data.quiet);
+    in_parallel(files, data, jobs, true, |files, data| {
+        let mut failures = vec![];
+        for source in files {
+            progress.step(&source.path);
+            match convert_file(source, data) {
+                Err(e) if is_not_utf8(&e) => warn_not_utf8(&source.path, data),
+                Err(e) => {
+                    eprintln!("Could not convert '{}': {e:#}", source.path.display());
+                    failures.push((source.path.clone(), e));
+                }
+                Ok(()) => (),
+            }
+        }
+        failures
+    })
+}
+
+fn main() -> Result<()> {
+    run(Args::parse_with_config(std::env::args_os())?)
+}
+
+fn run(mut args: Args) -> Result<()> {
+    if args.check {
+        // Checks convert everything in memory.
+        args.convert_all = true;
+        args.extract_ids = false;
+        args.dry_run = true;
+        args.force = true;
+    }
+    if !args.file_or_folder.exists() {
+        bail!("'{}' does not exist", args.file_or_folder.display());
+    }
+    if !args.graph && args.file_or_folder.join("logseq/config.edn").is_file() {
+        bail!(
+            "'{}' is the root of a logseq graph, pass its pages directory, a single page or add --graph",
+            args.file_or_folder.display()
+        );
+    }
+    if args.graph {
+        let dirs = graph::GraphDirs::discover(&args.file_or_folder)?;
+        args.file_or_folder = dirs.pages;
+        args.journals = args.journals.or(dirs.journals);
+        args.assets_dir = args.assets_dir.or(dirs.assets);
+    }
+    if args.vault.exists() && !args.vault.is_dir() {
+        bail!("The vault '{}' is not a directory", args.vault.display());
+    }
+    if args.stdout && args.file_or_folder.is_dir() {
+        bail!("--stdout can only be used with a single page");
+    }
+    if args.export_single.is_some() && !args.file_or_folder.is_dir() {
+        bail!("--export-single can only be used with a directory");
+    }
+    if args.title.is_some() && args.file_or_folder.is_dir() {
+        bail!("--title can only be used with a single page");
+    }
+    if !args.dry_run && !args.stdout {
+        std::fs::create_dir_all(&args.vault)
+            .with_context(|| anyhow!("Could not create the vault '{}'", args.vault.display()))?;
+    }
+
+    let replacements = args
+        .replacements
+        .iter()
+        .map(|(pattern, replacement)| {
+            let re = regex::Regex::new(pattern)
+                .with_context(|| anyhow!("Invalid replacement pattern '{pattern}'"))?;
+            Ok((re, replacement.clone()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let ids_file = args.ids_file.clone().unwrap_or_else(|| {
+        let vault_dir = args.vault.parent().unwrap_or_else(|| Path::new(""));
+        vault_dir.join("ids.json")
+    });
+    let manifest_file = args.vault.join(".logseq2obsdn-manifest.json");
+    let refs_file = std::fs::File::open(&ids_file)
+        .map_err(anyhow::Error::from)
+        .and_then(|f| Ok(serde_json::from_reader::<_, RefsFile>(BufReader::new(f))?))
+        .unwrap_or_default();
+
+    let mut data = Data {
+        page_path: PathBuf::new(),
+        page_dir: Path::new(&args.file_or_folder).parent().unwrap().to_owned(),
+        out_vault: args.vault.clone(),
+        routes: args.routes.clone(),
+        out_file: None,
+        out_extension: args.out_extension.trim_start_matches('.').to_owned(),
+        title_override: None,
+        files: vec![],
+        asset_dests: Default::default(),
+        page_dests: Default::default(),
+        collisions: BTreeSet::new(),
+        link_assets: args.link_assets,
+        dry_run: args.dry_run,
+        check: args.check,
+        stdout: args.stdout,
+        quiet: args.quiet,
+        verbose: args.verbose,
+        manifest: Arc::new(match args.force {
+            true => Manifest::default(),
+            false => Manifest::load(&manifest_file),
+        }),
+        converted: vec![],
+        refs_file,
+        config: page::Config {
+            callout_style: args.callout_style,
+            cloze_style: args.cloze_style,
+            block_props: args.block_props,
+            anchor_style: args.anchor_style,
+            task_dates: !args.no_task_dates,
+            rich_task_states: args.rich_task_states,
+            strike_done: args.strike_done,
+            inline_refs: args.inline_refs,
+            keep_logseq_ids: args.keep_logseq_ids,
+            drop_queries: args.drop_queries,
+            drop_renderers: args.drop_renderers,
+            drop_dynamic: args.drop_dynamic,
+            hard_breaks: args.hard_breaks,
+            keep_bold_headers_as_list: args.keep_bold_headers_as_list,
+            image_embeds: args.image_embeds,
+            captions_below: args.captions_below,
+            italic_captions: args.italic_captions,
+            tags_as_links: args.tags_as_links,
+            html_highlights: args.html_highlights,
+            flatten_empty_groups: args.flatten_empty_groups,
+            replacements,
+            public_as_publish: args.public_as_publish,
+            title_heading: args.title_heading,
+            hoist_block_tags: args.hoist_block_tags,
+            max_depth: args.max_depth,
+            post_processors: match args.trim_trailing_whitespace {
+                true => vec![Arc::new(TrimTrailingWhitespace)],
+                false => vec![],
+            },
+            attachments_dir: args.attachments_dir.clone(),
+            preserve_asset_tree: args.preserve_asset_tree,
+            assets_dir: args.assets_dir.clone(),
+            namespaces: args.namespaces,
+        },
+        journal_format: args.journals.as_ref().map(|_| args.journal_format.clone()),
+        journals_page: args.flatten_journals_into.clone(),
+        journal_dates: BTreeSet::new(),
+        exported_pages: BTreeMap::new(),
+        page_ids: vec![],
+        page_refs: vec![],
+        options: 0,
+        id_sources: BTreeMap::new(),
+        duplicate_ids: BTreeSet::new(),
+        referenced: BTreeSet::new(),
+        only_referenced_anchors: false,
+        unresolved: BTreeSet::new(),
+        warnings: BTreeSet::new(),
+        resolved_refs: 0,
+        unresolved_refs: 0,
+        unchanged_pages: 0,
+        dry_run_pages: 0,
+    };
+    data.options = data.options_hash();
+
+    let filter = PathFilter::new(&args.include, &args.exclude);
+    let mut journals = match &args.journals {
+        Some(dir) => journal_files(dir, &args.journal_format, &filter)?,
+        None => vec![],
+    };
+    data.journal_dates = journals
+        .iter()
+        .filter_map(|j| journal::parse_journal_date(&journal_date(&j.path)))
+        .collect();
+    if let (Some(title), Some(dir)) = (&args.flatten_journals_into, &args.journals) {
+        journals.sort_by_cached_key(|j| journal_date(&j.path));
+        for journal in &mut journals {
+            journal.title = Some(title.clone());
+            journal.merged_into = Some(dir.clone());
+        }
+    }
+
+    let pages = || -> Result<Vec<Source>> {
+        let mut files = page_files(&args.file_or_folder, &filter)?;
+        if let Some(prefix) = &args.merge_namespace {
+            mark_merged(&mut files, prefix, &args.file_or_folder);
+        }
+        Ok(files)
+    };
+
+    let mut failures = vec![];
+    if let Some(file) = &args.export_single {
+        let title = file.file_stem().unwrap_or_default().to_string_lossy();
+        let mut files = pages()?;
+        let mut titles = files
+            .iter()
+            .map(|s| (s.path.clone(), logseq_title(&s.path)))
+            .collect::<BTreeMap<_, _>>();
+        journals.sort_by_cached_key(|j| journal_date(&j.path));
+        titles.extend(
+            journals
+                .iter()
+                .map(|j| (j.path.clone(), journal_date(&j.path))),
+        );
+        files.append(&mut journals);
+        for source in &mut files {
+            source.title = Some(title.to_string());
+            source.merged_into = Some(args.file_or_folder.clone());
+        }
+        data.journals_page = Some(title.to_string());
+        data.exported_pages = titles
+            .values()
+            .map(|t| (t.trim().to_lowercase(), t.trim().to_owned()))
+            .collect();
+
+        failures.extend(extract_ids(&files, &mut data, args.jobs));
+        data.only_referenced_anchors = true;
+        let heading = |path: &Path, _: &str| format!("# {}", titles[path]);
+        if let Err(e) = convert_merged(&files, &title, heading, &mut data) {
+            eprintln!("{e:#}");
+            failures.push((args.file_or_folder.clone(), e));
+        }
+    } else if args.extract_ids || args.convert_all {
+        let files = pages()?;
+        let all_files = files.iter().chain(&journals).cloned().collect_vec();
+        failures.extend(extract_ids(&all_files, &mut data, args.jobs));
+
+        if args.extract_ids && args.dry_run {
+            println!("Would write ids to '{}'", ids_file.display());
+        } else if args.extract_ids {
+            let ids_w = BufWriter::new(std::fs::File::create(&ids_file)?);
+            serde_json::to_writer_pretty(ids_w, &data.refs_file)?;
+        }
+
+        if args.convert_all {
+            // All references are known now.
+            data.only_referenced_anchors = true;
+            failures.extend(convert_pages(&files, &args, &mut data));
+            failures.extend(convert_journals(&journals, &args, &mut data));
+        }
+    } else if args.file_or_folder.is_dir() {
+        failures.extend(convert_pages(&pages()?, &args, &mut data));
+        failures.extend(convert_journals(&journals, &args, &mut data));
+    } else {
+        let source = Source {
+            path: args.file_or_folder.clone(),
+            title: args.title.clone(),
+            merged_into: None,
+        };
+        convert_file(&source, &mut data)?;
+        failures.extend(convert_journals(&journals, &args, &mut data));
+    }
+
+    let report = Report {
+        converted_pages: match args.dry_run {
+            true => data.dry_run_pages,
+            false => data.converted.len(),
+        },
+        unchanged_pages: data.unchanged_pages,
+        copied_assets: data.converted.iter().map(|(_, e)| e.assets.len()).sum(),
+        resolved_refs: data.resolved_refs,
+        unresolved_refs: data.unresolved_refs,
+        failed_files: failures.iter().map(|(p, _)| p.clone()).collect(),
+    };
+    let converts = args.convert_all || !args.extract_ids;
+    if args.check {
+        eprintln!(
+            "Checked {} pages, {} warnings",
+            data.page_dests.lock().unwrap().len(),
+            data.warnings.len()
+        );
+    } else if converts && !args.stdout {
+        eprintln!("{}", report.summary(args.dry_run));
+    }
+    if let Some(path) = &args.report_json {
+        if args.dry_run || args.check {
+            println!("Would write the report to '{}'", path.display());
+        } else {
+            let w = BufWriter::new(std::fs::File::create(path)?);
+            serde_json::to_writer_pretty(w, &report)?;
+        }
+    }
+    if let Some(path) = &args.emit_mapping {
+        // Unreferenced blocks may have no anchor.
+        let mapping = data
+            .refs_file
+            .refs
+            .iter()
+            .filter(|(id, r)| !r.id.starts_with("#^") || page::Data::is_referenced(&data, id))
+            .map(|(id, r)| (id, Mapping::new(r)))
+            .collect::<BTreeMap<_, _>>();
+        if args.dry_run {
+            println!("Would write the mapping to '{}'", path.display());
+        } else {
+            let w = BufWriter::new(std::fs::File::create(path)?);
+            serde_json::to_writer_pretty(w, &mapping)?;
+        }
+    }
+
+    if converts {
+        let mut manifest = (*data.manifest).clone();
+        manifest.pages.extend(data.converted.drain(..));
+        for (page, entry) in manifest.remove_deleted() {
+            eprintln!(
+                "'{}' was deleted, its converted page '{}' is stale",
+                page.display(),
+                entry.output.display()
+            );
+        }
+        if !args.dry_run && !args.stdout {
+            manifest.save(&manifest_file)?;
+        }
+    }
+
+    if !failures.is_empty() {
+        eprintln!("{} files failed:", failures.len());
+        for (path, e) in &failures {
+            eprintln!("  '{}': {e:#}", path.display());
+        }
+    }
+
+    if !data.collisions.is_empty() {
+        eprintln!(
+            "{} pages were renamed, because other pages have the same file name:",
+            data.collisions.len()
+        );
+        for (page, out) in &data.collisions {
+            eprintln!("  '{}' to '{}'", page.display(), out.display());
+        }
+    }
+
+    if !data.unresolved.is_empty() {
+        eprintln!("{} unresolved block references:", data.unresolved.len());
+        for (title, id) in &data.unresolved {
+            eprintln!("  (({id})) in '{title}'");
+        }
+        if args.strict {
+            bail!("Some block references could not be resolved");
+        }
+    }
+    if args.strict && !data.collisions.is_empty() {
+        bail!("Some pages have the same file name");
+    }
+    if args.strict && !data.duplicate_ids.is_empty() {
+        bail!("Some block ids are used by several pages");
+    }
+    if !failures.is_empty() {
+        bail!("{} files could not be processed", failures.len());
+    }
+    let problems = data.unresolved.len() + data.collisions.len() + data.warnings.len();
+    if args.check && problems > 0 {
+        bail!("The check found {problems} problems");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("logseq2obsdn-test-{name}"));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[cfg(test)]
+fn write_file(path: &Path, content: &str) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, content).unwrap();
+}
+
+#[cfg(test)]
+fn read_refs(path: &Path) -> RefsFile {
+    serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+}
+
+#[test]
+fn test_extract_ids_merges() {
+    let dir = test_dir("extract-ids-merges");
+    let id_a = "6511c1e5-1111-4fd6-9c22-0b8f2ba6a111";
+    let id_b = "6511c1e5-2222-4fd6-9c22-0b8f2ba6a111";
+    write_file(&dir.join("a/A.md"), &format!("- a\n  id:: {id_a}"));
+    write_file(&dir.join("b/B.md"), &format!("- b\n  id:: {id_b}"));
+    let ids_file = dir.join("ids.json");
+
+    for pages in ["a", "b"] {
+        let args = Args::parse_from([
+            Path::new("logseq2obsdn"),
+            &dir.join(pages),
+            &dir.join("vault"),
+            Path::new("--extract-ids"),
+            Path::new("--ids-file"),
+            &ids_file,
+        ]);
+        run(args).unwrap();
+    }
+
+    let refs = read_refs(&ids_file);
+    assert_eq!(refs.refs[id_a].file, "A");
+    assert_eq!(refs.refs[id_b].file, "B");
+}
+
+#[test]
+fn test_ids_file_is_stable() {
+    let dir = test_dir("ids-file-stable");
+    let page = (0..50)
+        .map(|i| format!("- block {i}\n  id:: 6511c1e5-{i:04}-4fd6-9c22-0b8f2ba6a111"))
+        .join("\n");
+    write_file(&dir.join("pages/A.md"), &page);
+    let ids_file = dir.join("ids.json");
+
+    let mut outputs = vec![];
+    for _ in 0..2 {
+        let _ = std::fs::remove_file(&ids_file);
+        let args = Args::parse_from([
+            Path::new("logseq2obsdn"),
+            &dir.join("pages"),
+            &dir.join("vault"),
+            Path::new("--extract-ids"),
+        ]);
+        run(args).unwrap();
+        outputs.push(std::fs::read(&ids_file).unwrap());
+    }
+    assert_eq!(outputs[0], outputs[1]);
+}
+
+// Pages that aren't UTF-8 are skipped, a dangling symlink can't be read at all.
+#[test]
+fn test_extract_ids_continues_on_failure() {
+    let dir = test_dir("extract-ids-failure");
+    let id = "6511c1e5-1111-4fd6-9c22-0b8f2ba6a111";
+    write_file(&dir.join("pages/A.md"), &format!("- a\n  id:: {id}"));
+    std::fs::write(dir.join("pages/B.md"), b"- \xff\xfe").unwrap();
+    #[cfg(unix)]
+    symlink_file(&dir.join("missing.md"), &dir.join("pages/C.md")).unwrap();
+    let ids_file = dir.join("ids.json");
+
+    let args = Args::parse_from([
+        Path::new("logseq2obsdn"),
+        &dir.join("pages"),
+        &dir.join("vault"),
+        Path::new("--extract-ids"),
+        Path::new("--ids-file"),
+        &ids_file,
+    ]);
+    assert_eq!(run(args).is_err(), cfg!(unix));
+
+    let refs = read_refs(&ids_file);
+    assert_eq!(refs.refs[id].file, "A");
+
+    // Only UTF-8 errors, not other invalid data.
+    let not_utf8 = String::from_utf8(vec![0xff]).context("Could not read 'B.md'");
+    assert!(is_not_utf8(&not_utf8.unwrap_err()));
+    let invalid = std::io::Error::from(std::io::ErrorKind::InvalidData);
+    assert!(!is_not_utf8(&anyhow::Error::from(invalid)));
+}
+
+#[test]
+fn test_extract_ids_recursive() {
+    let dir = test_dir("extract-ids-recursive");
+    let id = "6511c1e5-1111-4fd6-9c22-0b8f2ba6a111";
+    write_file(
+        &dir.join("pages/sub/Foo.md"),
+        &format!("- foo\n  id:: {id}"),
+    );
+    let ids_file = dir.join("ids.json");
+
+    let args = Args::parse_from([
+        Path::new("logseq2obsdn"),
+        &dir.join("pages"),
+        &dir.join("vault"),
+        Path::new("--extract-ids"),
+        Path::new("--ids-file"),
+        &ids_file,
+    ]);
+    run(args).unwrap();
+
+    let refs = read_refs(&ids_file);
+    assert_eq!(refs.refs[id].file, "Foo");
+}
+
+#[test]
+fn test_attachments_dir() {
+    let dir = test_dir("attachments-dir");
+    write_file(&dir.join("pages/Foo.md"), "- ![a](../assets/x.png)");
+    write_file(&dir.join("pages/Bar.md"), "- ![b](../assets/sub/x.png)");
+    write_file(&dir.join("assets/x.png"), "foo");
+    write_file(&dir.join("assets/sub/x.png"), "bar");
+    std::fs::create_dir_all(dir.join("vault")).unwrap();
+
+    let args = Args::parse_from([
+        Path::new("logseq2obsdn"),
+        &dir.join("pages"),
+        &dir.join("vault"),
+        Path::new("--attachments-dir"),
+        Path::new("attachments"),
+    ]);
+    run(args).unwrap();
+
+    let vault = dir.join("vault");
+    let bar = std::fs::read_to_string(vault.join("Bar.md")).unwrap();
+    let foo = std::fs::read_to_string(vault.join("Foo.md")).unwrap();
+    assert!(bar.contains("![b](attachments/x.png)"), "{bar}");
+    assert!(foo.contains("![a](attachments/x 1.png)"), "{foo}");
+    let read = |name: &str| std::fs::read_to_string(vault.join("attachments").join(name)).unwrap();
+    assert_eq!(read("x.png"), "bar");
+    assert_eq!(read("x 1.png"), "foo");
+}
+
+#[test]
+fn test_absolute_assets() {
+    let dir = test_dir("absolute-assets");
+    let external = dir.join("external");
+    write_file(
+        &dir.join("pages/Foo.md"),
+        &format!(
+            "- ![a]({})\n- ![b](file://{})\n- ![c](http://example.com/c.png)",
+            external.join("x.png").display(),
+            external.join("my%20y.png").display()
+        ),
+    );
+    write_file(&external.join("x.png"), "x");
+    write_file(&external.join("my y.png"), "y");
+
+    let args = Args::parse_from([
+        Path::new("logseq2obsdn"),
+        &dir.join("pages"),
+        &dir.join("vault"),
+    ]);
+    run(args).unwrap();
+
+    let vault = dir.join("vault");
+    let foo = std::fs::read_to_string(vault.join("Foo.md")).unwrap();
+    assert_eq!(
+        foo,
+        "![a](assets/x.png)\n\n![b](assets/my y.png)\n\n![c](http://example.com/c.png)\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(vault.join("assets/x.png")).unwrap(),
+        "x"
+    );
+    assert_eq!(
+        std::fs::read_to_string(vault.join("assets/my y.png")).unwrap(),
+        "y"
+    );
+    assert_eq!(std::fs::read_dir(vault.join("assets")).unwrap().count(), 2);
+}
+
+#[test]
+fn test_asset_case() {
+    let dir = test_dir("asset-case");
+    write_file(
+        &dir.join("pages/Foo.md"),
+        "- ![a](../Assets/X.PNG)\n- ![b](../assets/Sub/y.png)",
+    );
+    write_file(&dir.join("assets/x.png"), "x");
+    write_file(&dir.join("assets/sub/Y.png"), "y");
+    if dir.join("assets/X.PNG").exists() {
+        // The file system doesn't care about case either.
+        return;
+    }
+
+    run(Args::parse_from([
+        "logseq2obsdn".into(),
+        dir.join("pages"),
+        dir.join("vault"),
+    ]))
+    .unwrap();
+    let vault = dir.join("vault");
+    assert_eq!(
+        std::fs::read_to_string(vault.join("Foo.md")).unwrap(),
+        "![a](assets/x.png)\n\n![b](assets/sub/Y.png)\n"
+    );
+    assert!(vault.join("assets/x.png").exists());
+    assert!(vault.join("assets/sub/Y.png").exists());
+}
+
+#[test]
+fn test_assets_dir() {
+    let dir = test_dir("assets-dir");
+    write_file(
+        &dir.join("pages/Foo.md"),
+        "- ![a](../../assets/x.png)\n- ![b](../assets/y.png)",
+    );
+    write_file(&dir.join("assets/x.png"), "x");
+    write_file(&dir.join("assets/y.png"), "y");
+
+    let args = |assets_dir: bool| {
+        let mut args = vec![
+            "logseq2obsdn".into(),
+            dir.join("pages"),
+            dir.join("vault"),
+            "--force".into(),
+        ];
+        if assets_dir {
+            args.extend(["--assets-dir".into(), dir.join("assets")]);
+        }
+        Args::parse_from(args)
+    };
+    let vault = dir.join("vault");
+    let foo = || std::fs::read_to_string(vault.join("Foo.md")).unwrap();
+    run(args(false)).unwrap();
+    assert_eq!(foo(), "![a](../../assets/x.png)\n\n![b](assets/y.png)\n");
+    run(args(true)).unwrap();
+    assert_eq!(foo(), "![a](assets/x.png)\n\n![b](assets/y.png)\n");
+    assert_eq!(
+        std::fs::read_to_string(vault.join("assets/x.png")).unwrap(),
+        "x"
+    );
+}
+
+#[test]
+fn test_preserve_asset_tree() {
+    let dir = test_dir("preserve-asset-tree");
+    write_file(&dir.join("pages/Foo.md"), "- ![a](../assets/sub/x.png)");
+    write_file(&dir.join("assets/sub/x.png"), "x");
+
+    let args = |attachments: bool| {
+        let mut args = vec![
+            "logseq2obsdn".into(),
+            dir.join("pages"),
+            dir.join("vault"),
+            "--force".into(),
+            "--preserve-asset-tree".into(),
+        ];
+        if attachments {
+            args.extend(["--attachments-dir".into(), "attachments".into()]);
+        }
+        Args::parse_from(args)
+    };
+    let vault = dir.join("vault");
+    let foo = || std::fs::read_to_string(vault.join("Foo.md")).unwrap();
+    run(args(false)).unwrap();
+    assert_eq!(foo(), "![a](assets/sub/x.png)\n");
+    assert!(vault.join("assets/sub/x.png").exists());
+    run(args(true)).unwrap();
+    assert_eq!(foo(), "![a](attachments/sub/x.png)\n");
+    assert!(vault.join("attachments/sub/x.png").exists());
+}
+
+#[test]
+fn test_check() {
+    let dir = test_dir("check");
+    write_file(&dir.join("pages/Foo.md"), "- ![a](../assets/x.png)");
+    write_file(&dir.join("pages/Bar.md"), "- bar");
+
+    let args = || {
+        Args::parse_from([
+            Path::new("logseq2obsdn"),
+            &dir.join("pages"),
+            &dir.join("vault"),
+            Path::new("--check"),
+        ])
+    };
+    assert!(run(args()).is_err());
+    assert!(!dir.join("vault").exists());
+
+    write_file(&dir.join("assets/x.png"), "x");
+    run(args()).unwrap();
+    assert!(!dir.join("vault").exists());
+    assert!(!dir.join("ids.json").exists());
+}
+
+#[test]
+fn test_config() {
+    let dir = test_dir("config");
+    write_file(&dir.join("pages/Foo.md"), "- ![a](../assets/x.png)");
+    write_file(&dir.join("assets/x.png"), "foo");
+    let config = dir.join("config.json");
+    write_file(
+        &config,
+        r#"{ "attachments-dir": "attachments", "out-extension": "txt" }"#,
+    );
+
+    let args = Args::parse_with_config([
+        Path::new("logseq2obsdn"),
+        &dir.join("pages"),
+        &dir.join("vault"),
+        Path::new("--config"),
+        &config,
+        Path::new("--out-extension"),
+        Path::new("markdown"),
+    ])
+    .unwrap();
+    assert_eq!(
+        args.attachments_dir.as_deref(),
+        Some(Path::new("attachments"))
+    );
+    run(args).unwrap();
+
+    let foo = std::fs::read_to_string(dir.join("vault/Foo.markdown")).unwrap();
+    assert!(foo.contains("![a](attachments/x.png)"), "{foo}");
+    assert!(dir.join("vault/attachments/x.png").exists());
+
+    write_file(&config, r#"{ "attachment-dir": "attachments" }"#);
+    assert!(Args::parse_with_config([
+        Path::new("logseq2obsdn"),
+        &dir.join("pages"),
+        &dir.join("vault"),
+        Path::new("--config"),
+        &config,
+    ])
+    .is_err());
+}
+
+#[test]
+fn test_colliding_asset_names() {
+    let dir = test_dir("colliding-asset-names");
+    write_file(&dir.join("pages/A.md"), "- ![a](../assets/logo.png)");
+    write_file(&dir.join("pages/sub/B.md"), "- ![b](../assets/logo.png)");
+    write_file(&dir.join("assets/logo.png"), "a");
+    write_file(&dir.join("pages/assets/logo.png"), "b");
+    std::fs::create_dir_all(dir.join("vault")).unwrap();
+
+    let args = Args::parse_from([
+        Path::new("logseq2obsdn"),
+        &dir.join("pages"),
+        &dir.join("vault"),
+    ]);
+    run(args).unwrap();
+
+    let vault = dir.join("vault");
+    let a = std::fs::read_to_string(vault.join("A.md")).unwrap();
+    let b = std::fs::read_to_string(vault.join("B.md")).unwrap();
+    assert!(a.contains("![a](assets/logo.png)"), "{a}");
+    assert!(b.contains("![b](assets/logo 1.png)"), "{b}");
+    let read = |name: &str| std::fs::read_to_string(vault.join("assets").join(name)).unwrap();
+    assert_eq!(read("logo.png"), "a");
+    assert_eq!(read("logo 1.png"), "b");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_link_assets() {
+    let dir = test_dir("link-assets");
+    write_file(&dir.join("pages/Foo.md"), "- ![a](../assets/x.png)");
+    write_file(&dir.join("assets/x.png"), "foo");
+    std::fs::create_dir_all(dir.join("vault")).unwrap();
+
+    let args = Args::parse_from([
+        Path::new("logseq2obsdn"),
+        &dir.join("pages"),
+        &dir.join("vault"),
+        Path::new("--link-assets"),
+    ]);
+    run(args).unwrap();
+
+    let dest = dir.join("vault/assets/x.png");
+    assert!(dest.is_symlink());
+    assert_eq!(std::fs::read_to_string(dest).unwrap(), "foo");
+}
+
+#[test]
+fn test_dry_run() {
+    let dir = test_dir("dry-run");
+    let id = "6511c1e5-1111-4fd6-9c22-0b8f2ba6a111";
+    write_file(
+        &dir.join("pages/Foo.md"),
+        &format!("- ![a](../assets/x.png)\n  id:: {id}"),
+    );
+    write_file(&dir.join("assets/x.png"), "foo");
+    std::fs::create_dir_all(dir.join("vault")).unwrap();
+
+    let args = Args::parse_from([
+        Path::new("logseq2obsdn"),
+        &dir.join("pages"),
+        &dir.join("vault"),
+        Path::new("--convert-all"),
+        Path::new("--extract-ids"),
+        Path::new("--dry-run"),
+        Path::new("--report-json"),
+        &dir.join("report.json"),
+    ]);
+    run(args).unwrap();
+
+    assert_eq!(std::fs::read_dir(dir.join("vault")).unwrap().count(), 0);
+    assert!(!dir.join("ids.json").exists());
+    assert!(!dir.join("report.json").exists());
+}
+
+#[test]
+fn test_summary() {
+    let report = Report {
+        converted_pages: 2,
+        unchanged_pages: 1,
+        copied_assets: 3,
+        resolved_refs: 4,
+        unresolved_refs: 1,
+        failed_files: vec![],
+    };
+    assert_eq!(
+        report.summary(false),
+        "Converted 2 pages (1 unchanged), copied 3 assets, resolved 4 of 5 block references, 0 files failed"
+    );
+    assert_eq!(
+        report.summary(true),
+        "Would convert 2 pages (1 unchanged), resolved 4 of 5 block references, 0 files failed"
+    );
+}
+
+#[test]
+fn test_jobs() {
+    let dir = test_dir("jobs");
+    let id = |i: usize| format!("6511c1e5-1111-4fd6-9c22-{i:012}");
+    for i in 0..1000 {
+        write_file(
+            &dir.join(format!("pages/Page {i}.md")),
+            &format!(
+                "- block {i}\n  id:: {}\n- see (({}))",
+                id(i),
+                id((i + 1) % 1000)
+            ),
+        );
+    }
+
+    let convert = |jobs: &str| {
+        let vault = dir.join(format!("vault-{jobs}"));
+        std::fs::create_dir_all(&vault).unwrap();
+        let args = Args::parse_from([
+            Path::new("logseq2obsdn"),
+            &dir.join("pages"),
+            &vault,
+            Path::new("--convert-all"),
+            Path::new("--jobs"),
+            Path::new(jobs),
+        ]);
+        let start = std::time::Instant::now();
+        run(args).unwrap();
+        eprintln!("--jobs {jobs}: {:?}", start.elapsed());
+        vault
+    };
+    let serial = convert("1");
+    let parallel = convert("4");
+
+    for i in 0..1000 {
+        let name = format!("Page {i}.md");
+        let serial = std::fs::read_to_string(serial.join(&name)).unwrap();
+        let parallel = std::fs::read_to_string(parallel.join(&name)).unwrap();
+        assert_eq!(serial, parallel);
+        assert!(!serial.contains("(("), "{serial}");
+    }
+}
+
+#[test]
+fn test_skip_unchanged() {
+    let dir = test_dir("skip-unchanged");
+    let page = dir.join("pages/Foo.md");
+    write_file(&page, "- foo");
+    std::fs::create_dir_all(dir.join("vault")).unwrap();
+    let out = dir.join("vault/Foo.md");
+
+    let convert = |force: bool| {
+        let mut args = vec![
+            dir.join("logseq2obsdn"),
+            dir.join("pages"),
+            dir.join("vault"),
+        ];
+        if force {
+            args.push("--force".into());
+        }
+        run(Args::parse_from(args)).unwrap();
+        std::fs::read_to_string(&out).unwrap()
+    };
+    assert_eq!(convert(false), "- foo\n");
+
+    std::fs::write(&out, "edited").unwrap();
+    assert_eq!(convert(false), "edited");
+    assert_eq!(convert(true), "- foo\n");
+
+    std::fs::write(&page, "- bar").unwrap();
+    let modified = std::time::SystemTime::now() + std::time::Duration::from_secs(10);
+    std::fs::File::options()
+        .write(true)
+        .open(&page)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+    assert_eq!(convert(false), "- bar\n");
+}
+
+#[test]
+fn test_skip_unchanged_options_and_refs() {
+    let dir = test_dir("skip-unchanged-options");
+    let id = "6511c1e5-1111-4fd6-9c22-0b8f2ba6a111";
+    let a = dir.join("pages/A.md");
+    write_file(&a, &format!("- a\n  id:: {id}"));
+    write_file(&dir.join("pages/B.md"), &format!("- DONE (({id}))"));
+    let b = dir.join("vault/B.md");
+
+    let convert = |strike: bool| {
+        let mut args = vec![
+            dir.join("logseq2obsdn"),
+            dir.join("pages"),
+            dir.join("vault"),
+            "--convert-all".into(),
+        ];
+        if strike {
+            args.push("--strike-done".into());
+        }
+        run(Args::parse_from(args)).unwrap();
+        std::fs::read_to_string(&b).unwrap()
+    };
+    let before = convert(false);
+    assert!(before.starts_with("- [x] [[A#^"), "{before}");
+    let struck = convert(true);
+    assert!(struck.starts_with("- [x] ~~[[A#^"), "{struck}");
+
+    // B didn't change, but the anchor of the block it references did.
+    std::fs::write(&a, format!("- changed\n  id:: {id}")).unwrap();
+    let modified = std::time::SystemTime::now() + std::time::Duration::from_secs(10);
+    std::fs::File::options()
+        .write(true)
+        .open(&a)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+    let changed = convert(true);
+    assert_ne!(changed, struck);
+    let anchor = std::fs::read_to_string(dir.join("vault/A.md")).unwrap();
+    let anchor = anchor.trim_end().rsplit(' ').next().unwrap().to_owned();
+    assert_eq!(changed, format!("- [x] ~~[[A#{anchor}]]~~\n"));
+}
+
+#[test]
+fn test_sanitize_file_name() {
+    assert_eq!(sanitize_file_name("Meeting: Notes"), "Meeting_ Notes");
+    assert_eq!(sanitize_file_name("Etc."), "Etc");
+    assert_eq!(sanitize_file_name("A/B?"), "A_B_");
+    assert_eq!(sanitize_file_name("Plain title"), "Plain title");
+}
+
+#[test]
+fn test_sanitized_title_keeps_links() {
+    let dir = test_dir("sanitized-title");
+    let id = "6511c1e5-1111-4fd6-9c22-0b8f2ba6a111";
+    write_file(
+        &dir.join("pages/Meeting.md"),
+        &format!("title:: Meeting: Notes\n\n- foo\n  id:: {id}"),
+    );
+    std::fs::create_dir_all(dir.join("vault")).unwrap();
+    let ids_file = dir.join("ids.json");
+
+    let args = Args::parse_from([
+        Path::new("logseq2obsdn"),
+        &dir.join("pages"),
+        &dir.join("vault"),
+        Path::new("--convert-all"),
+        Path::new("--extract-ids"),
+    ]);
+    run(args).unwrap();
+
+    assert!(dir.join("vault/Meeting_ Notes.md").exists());
+    assert_eq!(read_refs(&ids_file).refs[id].file, "Meeting: Notes");
+}
+
+#[test]
+fn test_out_extension() {
+    let dir = test_dir("out-extension");
+    let id = "6511c1e5-1111-4fd6-9c22-0b8f2ba6a111";
+    write_file(&dir.join("pages/A.md"), &format!("- a\n  id:: {id}"));
+    write_file(&dir.join("pages/B.md"), &format!("- (({id}))"));
+
+    let args = Args::parse_from([
+        Path::new("logseq2obsdn"),
+        &dir.join("pages"),
+        &dir.join("vault"),
+        Path::new("--convert-all"),
+        Path::new("--out-extension"),
+        Path::new("markdown"),
+    ]);
+    run(args).unwrap();
+
+    assert!(dir.join("vault/A.markdown").exists());
+    let b = std::fs::read_to_string(dir.join("vault/B.markdown")).unwrap();
+    assert!(b.starts_with("- [[A#^"), "{b}");
+}
+
+#[test]
+fn test_flatten_journals() {
+    let dir = test_dir("flatten-journals");
+    let id = "6511c1e5-1111-4fd6-9c22-0b8f2ba6a111";
+    write_file(&dir.join("pages/Page.md"), &format!("- see (({id}))"));
+    write_file(&dir.join("journals/2023_10_05.md"), "- ## october");
+    write_file(
+        &dir.join("journals/2023_01_02.md"),
+        &format!("- january\n  id:: {id}"),
+    );
+    write_file(&dir.join("journals/2023_03_04.md"), "tags:: day\n\n- march");
+
+    let args = Args::parse_from([
+        Path::new("logseq2obsdn"),
+        &dir.join("pages"),
+        &dir.join("vault"),
+        Path::new("--convert-all"),
+        Path::new("--journals"),
+        &dir.join("journals"),
+        Path::new("--flatten-journals-into"),
+        Path::new("Journal"),
+    ]);
+    run(args).unwrap();
+
+    let journal = std::fs::read_to_string(dir.join("vault/Journal.md")).unwrap();
+    let anchor = journal.lines().nth(2).unwrap().rsplit(' ').next().unwrap();
+    assert_eq!(
+        journal,
+        format!("## 2023-01-02\n\n- january {anchor}\n\n## 2023-03-04\n\n- march\n\n## 2023-10-05\n\n### october\n")
+    );
+    let page = std::fs::read_to_string(dir.join("vault/Page.md")).unwrap();
+    assert_eq!(page, format!("- see [[Journal#{anchor}]]\n"));
+    assert!(!dir.join("vault/2023-01-02.md").exists());
+}
+
+#[test]
+fn test_flatten_journals_anchor_clash() {
+    let dir = test_dir("flatten-journals-anchor-clash");
+    let ids = [
+        "6511c1e5-1111-4fd6-9c22-0b8f2ba6a111",
+        "6511c1e5-2222-4fd6-9c22-0b8f2ba6a222",
+    ];
+    for (date, id) in ["2023_01_02", "2023_01_03"].iter().zip(ids) {
+        write_file(
+            &dir.join(format!("journals/{date}.md")),
+            &format!("- standup notes\n  id:: {id}"),
+        );
+    }
+    write_file(
+        &dir.join("pages/Page.md"),
+        &format!("- (({})) and (({}))", ids[0], ids[1]),
+    );
+
+    let args = Args::parse_from([
+        Path::new("logseq2obsdn"),
+        &dir.join("pages"),
+        &dir.join("vault"),
+        Path::new("--convert-all"),
+        Path::new("--journals"),
+        &dir.join("journals"),
+        Path::new("--flatten-journals-into"),
+        Path::new("Journal"),
+        Path::new("--anchor-style"),
+        Path::new("slug"),
+    ]);
+    run(args).unwrap();
+
+    let journal = std::fs::read_to_string(dir.join("vault/Journal.md")).unwrap();
+    assert_eq!(
+        journal,
+        "## 2023-01-02\n\n- standup notes ^standup-notes\n\n## 2023-01-03\n\n- standup notes ^standup-notes-1\n"
+    );
+    let page = std::fs::read_to_string(dir.join("vault/Page.md")).unwrap();
+    assert_eq!(
+        page,
+        "- [[Journal#^standup-notes]] and [[Journal#^standup-notes-1]]\n"
+    );
+}
+
+#[test]
+fn test_referenced_anchors() {
+    let dir = test_dir("referenced-anchors");
+    let (id_a, id_b) = (
+        "6511c1e5-1111-4fd6-9c22-0b8f2ba6a111",
+        "6511c1e5-2222-4fd6-9c22-0b8f2ba6a222",
+    );
+    write_file(
+        &dir.join("pages/Foo.md"),
+        &format!("- a\n  id:: {id_a}\n- b\n  id:: {id_b}"),
+    );
+    write_file(&dir.join("pages/Bar.md"), &format!("- see (({id_a}))"));
+
+    let args = || {
+        Args::parse_from([
+            Path::new("logseq2obsdn"),
+            &dir.join("pages"),
+            &dir.join("vault"),
+            Path::new("--convert-all"),
+        ])
+    };
+    run(args()).unwrap();
+    let foo = || std::fs::read_to_string(dir.join("vault/Foo.md")).unwrap();
+    // The anchors of the links in `Bar`.
+    let anchors = || {
+        let bar = std::fs::read_to_string(dir.join("vault/Bar.md")).unwrap();
+        bar.split("[[Foo#")
+            .skip(1)
+            .map(|l| l.split("]]").next().unwrap().to_owned())
+            .collect_vec()
+    };
+    let anchor_a = anchors()[0].clone();
+    assert_eq!(foo(), format!("- a {anchor_a}\n- b\n"));
+
+    // Foo didn't change, but its second block is referenced now.
+    write_file(
+        &dir.join("pages/Bar.md"),
+        &format!("- see (({id_a})) and (({id_b}))"),
+    );
+    run(args()).unwrap();
+    let anchor_b = anchors()[1].clone();
+    assert_eq!(foo(), format!("- a {anchor_a}\n- b {anchor_b}\n"));
+}
+
+#[test]
+fn test_numeric_titles() {
+    let dir = test_dir("numeric-titles");
+    let ids = [
+        "6511c1e5-1111-4fd6-9c22-0b8f2ba6a111",
+        "6511c1e5-2222-4fd6-9c22-0b8f2ba6a222",
+        "6511c1e5-3333-4fd6-9c22-0b8f2ba6a333",
+    ];
+    let titles = ["2023", "1.5", "v1.0"];
+    for (title, id) in titles.iter().zip(ids) {
+        write_file(
+            &dir.join(format!("pages/{title}.md")),
+            &format!("- {title}\n  id:: {id}"),
+        );
+    }
+    write_file(
+        &dir.join("pages/Page.md"),
+        &ids.iter().map(|id| format!("- (({id}))")).join("\n"),
+    );
+
+    let args = Args::parse_from([
+        Path::new("logseq2obsdn"),
+        &dir.join("pages"),
+        &dir.join("vault"),
+        Path::new("--convert-all"),
+    ]);
+    run(args).unwrap();
+
+    let page = std::fs::read_to_string(dir.join("vault/Page.md")).unwrap();
+    for (title, line) in titles.iter().zip(page.lines()) {
+        assert!(dir.join(format!("vault/{title}.md")).exists(), "{title}");
+        assert!(line.starts_with(&format!("- [[{title}#^")), "{line}");
+    }
+    let claims = Mutex::new(HashMap::new());
+    let claim = |src: &str| claim_path(&claims, Path::new(src), PathBuf::from("1.5.md"));
+    assert_eq!(claim("a"), (PathBuf::from("1.5.md"), 0));
+    assert_eq!(claim("b"), (PathBuf::from("1.5 1.md"), 1));
+}
+
+#[test]
+fn test_merge_namespace() {
+    let dir = test_dir("merge-namespace");
+    let id = "6511c1e5-1111-4fd6-9c22-0b8f2ba6a111";
+    write_file(&dir.join("pages/Page.md"), &format!("- see (({id}))"));
+    write_file(
+        &dir.join("pages/Project___Beta.md"),
+        &format!("- beta\n  id:: {id}"),
+    );
+    write_file(
+        &dir.join("pages/alpha.md"),
+        "title:: Project/Alpha\ntags:: project\n\n- alpha",
+    );
+    write_file(
+        &dir.join("pages/gamma.md"),
+        "---\nfoo: bar\n---\ntitle:: Project/Gamma\n\n- gamma",
+    );
+
+    let args = Args::parse_from([
+        Path::new("logseq2obsdn"),
+        &dir.join("pages"),
+        &dir.join("vault"),
+        Path::new("--convert-all"),
+        Path::new("--merge-namespace"),
+        Path::new("Project/"),
+    ]);
+    run(args).unwrap();
+
+    let merged = std::fs::read_to_string(dir.join("vault/Project.md")).unwrap();
+    let anchor = merged.lines().nth(6).unwrap().rsplit(' ').next().unwrap();
+    assert_eq!(
+        merged,
+        format!("## Alpha\n\n- alpha\n\n## Beta\n\n- beta {anchor}\n\n## Gamma\n\n- gamma\n")
+    );
+    let page = std::fs::read_to_string(dir.join("vault/Page.md")).unwrap();
+    assert_eq!(page, format!("- see [[Project#{anchor}]]\n"));
+    assert!(!dir.join("vault/Project___Beta.md").exists());
+    assert!(!dir.join("vault/Project/Alpha.md").exists());
+    assert!(!dir.join("vault/gamma.md").exists());
+}
+
+#[test]
+fn test_merge_namespace_anchor_clash() {
+    let dir = test_dir("merge-namespace-anchor-clash");
+    let ids = [
+        "6511c1e5-1111-4fd6-9c22-0b8f2ba6a111",
+        "6511c1e5-2222-4fd6-9c22-0b8f2ba6a222",
+    ];
+    for (title, id) in ["Alpha", "Beta"].iter().zip(ids) {
+        write_file(
+            &dir.join(format!("pages/Project___{title}.md")),
+            &format!("- standup notes\n  id:: {id}"),
+        );
+    }
+    write_file(
+        &dir.join("pages/Page.md"),
+        &format!("- (({})) and (({}))", ids[0], ids[1]),
+    );
+
+    let args = Args::parse_from([
+        Path::new("logseq2obsdn"),
+        &dir.join("pages"),
+        &dir.join("vault"),
+        Path::new("--convert-all"),
+        Path::new("--merge-namespace"),
+        Path::new("Project/"),
+    ]);
+    run(args).unwrap();
+
+    let merged = std::fs::read_to_string(dir.join("vault/Project.md")).unwrap();
+    let anchors = merged
+        .lines()
+        .filter_map(|l| l.strip_prefix("- standup notes "))
+        .collect_vec();
+    assert_eq!(anchors.len(), 2, "{merged}");
+    assert_ne!(anchors[0], anchors[1]);
+    let page = std::fs::read_to_string(dir.join("vault/Page.md")).unwrap();
+    assert_eq!(
+        page,
+        format!(
+            "- [[Project#{}]] and [[Project#{}]]\n",
+            anchors[0], anchors[1]
+        )
+    );
+}
+
+#[test]
+fn test_journal_links() {
+    let dir = test_dir("journal-links");
+    write_file(
+        &dir.join("pages/Page.md"),
+        "- [[Oct 5th, 2023]]\n- [[Thursday, October 5th, 2023]]\n- [[Octopus]]\n- [[Oct 6th, 2023]]",
+    );
+    write_file(&dir.join("journals/2023_10_05.md"), "- october");
+
+    let args = |flatten: bool| {
+        let mut args = vec![
+            "logseq2obsdn".into(),
+            dir.join("pages"),
+            dir.join("vault"),
+            "--force".into(),
+            "--journals".into(),
+            dir.join("journals"),
+            "--journal-format".into(),
+            "DD.MM.YYYY".into(),
+        ];
+        if flatten {
+            args.extend(["--flatten-journals-into".into(), "Journal".into()]);
+        }
+        Args::parse_from(args)
+    };
+    run(args(false)).unwrap();
+    let page = std::fs::read_to_string(dir.join("vault/Page.md")).unwrap();
+    // There is no journal of the 6th.
+    assert_eq!(
+        page,
+        "- [[05.10.2023]]\n- [[05.10.2023]]\n- [[Octopus]]\n- [[Oct 6th, 2023]]\n"
+    );
+    assert!(dir.join("vault/05.10.2023.md").exists());
+
+    run(args(true)).unwrap();
+    let page = std::fs::read_to_string(dir.join("vault/Page.md")).unwrap();
+    assert!(page.starts_with("- [[Journal#2023-10-05]]\n"), "{page}");
+}
+
+#[test]
+fn test_alias_links() {
+    let dir = test_dir("alias-links");
+    write_file(
+        &dir.join("pages/Canonical.md"),
+        "alias:: Old Name\n\n- text",
+    );
+    write_file(&dir.join("pages/Page.md"), "- see [[old name]]");
+    let args = |input: PathBuf, extract: bool| {
+        let mut args = vec!["logseq2obsdn".into(), input, dir.join("vault")];
+        if extract {
+            args.push("--extract-ids".into());
+        }
+        Args::parse_from(args)
+    };
+
+    run(args(dir.join("pages"), true)).unwrap();
+    assert_eq!(
+        read_refs(&dir.join("ids.json")).aliases,
+        BTreeMap::from([("old name".to_owned(), "Canonical".to_owned())])
+    );
+    run(args(dir.join("pages/Page.md"), false)).unwrap();
+    let page = std::fs::read_to_string(dir.join("vault/Page.md")).unwrap();
+    assert_eq!(page, "- see [[Canonical|old name]]\n");
+}
+
+#[test]
+fn test_export_single() {
+    let dir = test_dir("export-single");
+    let id = "6511c1e5-1111-4fd6-9c22-0b8f2ba6a111";
+    write_file(
+        &dir.join("pages/Alpha.md"),
+        &format!("- see [[beta]] and [[Gamma]]\n- block\n  id:: {id}"),
+    );
+    write_file(
+        &dir.join("pages/Beta.md"),
+        &format!("- ## Part\n- (({id})) and [link](https://example.com)"),
+    );
+    let args = Args::parse_from([
+        "logseq2obsdn".into(),
+        dir.join("pages"),
+        dir.join("vault"),
+        "--export-single".into(),
+        "Archive.md".into(),
+    ]);
+    run(args).unwrap();
+
+    let page = std::fs::read_to_string(dir.join("vault/Archive.md")).unwrap();
+    let anchor = page
+        .split("- block ")
+        .nth(1)
+        .unwrap()
+        .lines()
+        .next()
+        .unwrap();
+    assert!(anchor.starts_with('^'), "{page}");
+    assert_eq!(
+        page,
+        format!("# Alpha\n\n- see [[#Beta|beta]] and [[Gamma]]\n- block {anchor}\n\n# Beta\n\n## Part\n\n- [[#{anchor}]] and [link](https://example.com)\n")
+    );
+    assert!(!dir.join("vault/Alpha.md").exists());
+}
+
+#[test]
+fn test_export_single_anchor_clash() {
+    let dir = test_dir("export-single-anchor-clash");
+    let ids = [
+        "6511c1e5-1111-4fd6-9c22-0b8f2ba6a111",
+        "6511c1e5-2222-4fd6-9c22-0b8f2ba6a222",
+    ];
+    for (title, id) in ["Alpha", "Beta"].iter().zip(ids) {
+        write_file(
+            &dir.join(format!("pages/{title}.md")),
+            &format!("- standup notes\n  id:: {id}"),
+        );
+    }
+    write_file(
+        &dir.join("pages/Gamma.md"),
+        &format!("- (({})) and (({}))", ids[0], ids[1]),
+    );
+    let args = Args::parse_from([
+        "logseq2obsdn".into(),
+        dir.join("pages"),
+        dir.join("vault"),
+        "--export-single".into(),
+        "Archive.md".into(),
+    ]);
+    run(args).unwrap();
+
+    let page = std::fs::read_to_string(dir.join("vault/Archive.md")).unwrap();
+    let anchors = page
+        .lines()
+        .filter_map(|l| l.strip_prefix("- standup notes "))
+        .collect_vec();
+    assert_eq!(anchors.len(), 2, "{page}");
+    assert_ne!(anchors[0], anchors[1]);
+    assert!(
+        page.contains(&format!("- [[#{}]] and [[#{}]]", anchors[0], anchors[1])),
+        "{page}"
+    );
+}
+
+#[test]
+fn test_title_fallback() {
+    let dir = test_dir("title-fallback");
+    let id = "6511c1e5-1111-4fd6-9c22-0b8f2ba6a111";
+    write_file(
+        &dir.join("pages/Foo.md"),
+        &format!("title:: \n\n- foo\n  id:: {id}"),
+    );
+    std::fs::create_dir_all(dir.join("vault")).unwrap();
+    let ids_file = dir.join("ids.json");
+
+    let args = Args::parse_from([
+        Path::new("logseq2obsdn"),
+        &dir.join("pages"),
+        &dir.join("vault"),
+        Path::new("--convert-all"),
+        Path::new("--extract-ids"),
+    ]);
+    run(args).unwrap();
+
+    assert!(dir.join("vault/Foo.md").exists());
+    assert_eq!(read_refs(&ids_file).refs[id].file, "Foo");
+
+    // Titles that are empty once sanitized fall back to the file name as well.
+    write_file(
+        &dir.join("pages/Foo.md"),
+        &format!("title:: ...\n\n- foo\n  id:: {id}"),
+    );
+    let args = Args::parse_from([
+        Path::new("logseq2obsdn"),
+        &dir.join("pages"),
+        &dir.join("vault"),
+        Path::new("--convert-all"),
+        Path::new("--extract-ids"),
+        Path::new("--force"),
+    ]);
+    run(args).unwrap();
+
+    assert!(!dir.join("vault/.md").exists());
+    assert!(dir.join("vault/Foo.md").exists());
+    assert_eq!(read_refs(&ids_file).refs[id].file, "Foo");
+}
+
+#[test]
+fn test_colliding_page_names() {
+    let dir = test_dir("colliding-page-names");
+    let id = "6511c1e5-1111-4fd6-9c22-0b8f2ba6a111";
+    write_file(&dir.join("pages/A.md"), "title:: Foo/Bar\n\n- a");
+    write_file(
+        &dir.join("pages/B.md"),
+        &format!("title:: Foo:Bar\n\n- b\n  id:: {id}"),
+    );
+    std::fs::create_dir_all(dir.join("vault")).unwrap();
+    let ids_file = dir.join("ids.json");
+
+    let args = |strict: bool| {
+        let mut args = vec![
+            dir.join("logseq2obsdn"),
+            dir.join("pages"),
+            dir.join("vault"),
+            "--convert-all".into(),
+            "--extract-ids".into(),
+            "--force".into(),
+        ];
+        if strict {
+            args.push("--strict".into());
+        }
+        Args::parse_from(args)
+    };
+    run(args(false)).unwrap();
+
+    let vault = dir.join("vault");
+    assert_eq!(
+        std::fs::read_to_string(vault.join("Foo_Bar.md")).unwrap(),
+        "- a\n"
+    );
+    assert!(std::fs::read_to_string(vault.join("Foo_Bar 1.md"))
+        .unwrap()
+        .starts_with("- b"));
+    assert_eq!(read_refs(&ids_file).refs[id].file, "Foo:Bar 1");
+
+    assert!(run(args(true)).is_err());
+}
+
+#[test]
+fn test_directory_input() {
+    let dir = test_dir("directory-input");
+    write_file(&dir.join("graph/pages/Foo.md"), "- foo");
+    write_file(&dir.join("graph/logseq/config.edn"), "{}");
+
+    let args = |input: &str| {
+        Args::parse_from([
+            Path::new("logseq2obsdn"),
+            &dir.join(input),
+            &dir.join("vault"),
+        ])
+    };
+    // Directories are converted without --extract-ids as well.
+    run(args("graph/pages")).unwrap();
+    assert!(dir.join("vault/Foo.md").exists());
+    assert!(!dir.join("ids.json").exists());
+
+    let e = run(args("graph")).unwrap_err().to_string();
+    assert!(e.contains("root of a logseq graph"), "{e}");
+    assert!(e.contains("--graph"), "{e}");
+}
+
+#[test]
+fn test_emit_mapping() {
+    let dir = test_dir("emit-mapping");
+    let id = "6511c1e5-1111-4fd6-9c22-0b8f2ba6a111";
+    write_file(
+        &dir.join("pages/Foo.md"),
+        &format!("- foo\n  id:: {id}\n- unreferenced\n  id:: 6511c1e5-3333-4fd6-9c22-0b8f2ba6a111"),
+    );
+    write_file(
+        &dir.join("pages/Bar.md"),
+        &format!("id:: 6511c1e5-2222-4fd6-9c22-0b8f2ba6a111\n\n- bar (({id}))"),
+    );
+    let mapping_file = dir.join("mapping.json");
+
+    let args = Args::parse_from([
+        Path::new("logseq2obsdn"),
+        &dir.join("pages"),
+        &dir.join("vault"),
+        Path::new("--convert-all"),
+        Path::new("--emit-mapping"),
+        &mapping_file,
+    ]);
+    run(args).unwrap();
+
+    let mapping: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&mapping_file).unwrap()).unwrap();
+    let anchor = mapping[id]["anchor"].as_str().unwrap();
+    assert!(anchor.starts_with('^'), "{mapping}");
+    assert_eq!(mapping[id]["file"], "Foo");
+    assert_eq!(mapping[id]["link"], format!("[[Foo#{anchor}]]"));
+    let bar = std::fs::read_to_string(dir.join("vault/Bar.md")).unwrap();
+    assert!(bar.contains(&format!("[[Foo#{anchor}]]")), "{bar}");
+    assert!(mapping
+        .get("6511c1e5-3333-4fd6-9c22-0b8f2ba6a111")
+        .is_none());
+    assert_eq!(
+        mapping["6511c1e5-2222-4fd6-9c22-0b8f2ba6a111"]["link"],
+        "[[Bar]]"
+    );
+}
+
+#[test]
+fn test_graph_root() {
+    let dir = test_dir("graph-root");
+    let graph = dir.join("graph");
+    write_file(
+        &graph.join("logseq/config.edn"),
+        "{:journals-directory \"daily\"}",
+    );
+    write_file(&graph.join("pages/Foo.md"), "- see [[Oct 5th, 2023]]");
+    write_file(&graph.join("daily/2023_10_05.md"), "- today");
+
+    let args = Args::parse_from([
+        Path::new("logseq2obsdn"),
+        &graph,
+        &dir.join("vault"),
+        Path::new("--graph"),
+        Path::new("--convert-all"),
+    ]);
+    run(args).unwrap();
+
+    assert!(dir.join("vault/Foo.md").exists());
+    assert_eq!(
+        std::fs::read_to_string(dir.join("vault/2023-10-05.md")).unwrap(),
+        "- today\n"
+    );
+}
+
+#[test]
+fn test_duplicate_ids() {
+    let dir = test_dir("duplicate-ids");
+    let id = "6511c1e5-1111-4fd6-9c22-0b8f2ba6a111";
+    for page in ["A", "B", "C"] {
+        write_file(
+            &dir.join(format!("pages/{page}.md")),
+            &format!("- {page}\n  id:: {id}"),
+        );
+    }
+    write_file(&dir.join("pages/D.md"), &format!("- see (({id}))"));
+    std::fs::create_dir_all(dir.join("vault")).unwrap();
+    let ids_file = dir.join("ids.json");
+
+    let args = |jobs: &str, strict: bool| {
+        let mut args = vec![
+            dir.join("logseq2obsdn"),
+            dir.join("pages"),
+            dir.join("vault"),
+            "--convert-all".into(),
+            "--extract-ids".into(),
+            "--force".into(),
+            "--jobs".into(),
+            jobs.into(),
+        ];
+        if strict {
+            args.push("--strict".into());
+        }
+        Args::parse_from(args)
+    };
+    for jobs in ["1", "3"] {
+        run(args(jobs, false)).unwrap();
+        assert_eq!(read_refs(&ids_file).refs[id].file, "A");
+        let d = std::fs::read_to_string(dir.join("vault/D.md")).unwrap();
+        assert!(d.starts_with("- see [[A#^"), "{d}");
+    }
+
+    assert_eq!(
+        run(args("1", true)).unwrap_err().to_string(),
+        "Some block ids are used by several pages"
+    );
+}
+
+#[test]
+fn test_composed_file_names() {
+    let dir = test_dir("composed-file-names");
+    let id = "6511c1e5-1111-4fd6-9c22-0b8f2ba6a111";
+    write_file(
+        &dir.join("pages/Cafe.md"),
+        &format!("title:: Cafe\u{301}\n\n- foo\n  id:: {id}"),
+    );
+    std::fs::create_dir_all(dir.join("vault")).unwrap();
+    let ids_file = dir.join("ids.json");
+
+    let args = Args::parse_from([
+        Path::new("logseq2obsdn"),
+        &dir.join("pages"),
+        &dir.join("vault"),
+        Path::new("--convert-all"),
+        Path::new("--extract-ids"),
+    ]);
+    run(args).unwrap();
+
+    assert!(dir.join("vault/Café.md").exists());
+    assert_eq!(read_refs(&ids_file).refs[id].file, "Café");
+}
+
+#[test]
+fn test_encoded_titles() {
+    let dir = test_dir("encoded-titles");
+    let ids = [
+        "6511c1e5-1111-4fd6-9c22-0b8f2ba6a111",
+        "6511c1e5-2222-4fd6-9c22-0b8f2ba6a222",
+    ];
+    write_file(
+        &dir.join("pages/Projects%2FAlpha.md"),
+        &format!("- alpha\n  id:: {}", ids[0]),
+    );
+    write_file(
+        &dir.join("pages/Projects___Beta.md"),
+        &format!("title:: Projects%2FBeta\n\n- beta\n  id:: {}", ids[1]),
+    );
+    write_file(
+        &dir.join("pages/Refs.md"),
+        &format!("- (({}))\n- (({}))", ids[0], ids[1]),
+    );
+    std::fs::create_dir_all(dir.join("vault")).unwrap();
+    let ids_file = dir.join("ids.json");
+
+    let args = Args::parse_from([
+        Path::new("logseq2obsdn"),
+        &dir.join("pages"),
+        &dir.join("vault"),
+        Path::new("--convert-all"),
+        Path::new("--extract-ids"),
+        Path::new("--namespaces"),
+    ]);
+    run(args).unwrap();
+
+    let refs = read_refs(&ids_file).refs;
+    assert_eq!(refs[ids[0]].file, "Projects/Alpha");
+    assert_eq!(refs[ids[1]].file, "Projects/Beta");
+    assert!(dir.join("vault/Projects/Alpha.md").exists());
+    assert!(dir.join("vault/Projects/Beta.md").exists());
+    let refs_page = std::fs::read_to_string(dir.join("vault/Refs.md")).unwrap();
+    assert!(refs_page.contains("[[Projects/Alpha#^"));
+    assert!(refs_page.contains("[[Projects/Beta#^"));
+}
+
+#[test]
+fn test_routes() {
+    let dir = test_dir("routes");
+    write_file(&dir.join("pages/A.md"), "title:: Work/Plan\n\n- plan");
+    write_file(&dir.join("pages/B.md"), "title:: Personal/Diary\n\n- diary");
+    write_file(&dir.join("pages/C.md"), "- other");
+    let config = dir.join("config.json");
+    let routes = serde_json::json!({
+        "routes": { "Work/": dir.join("work"), "Personal/": dir.join("personal") }
+    });
+    write_file(&config, &routes.to_string());
+
+    let args = Args::parse_with_config([
+        Path::new("logseq2obsdn"),
+        &dir.join("pages"),
+        &dir.join("vault"),
+        Path::new("--namespaces"),
+        Path::new("--config"),
+        &config,
+    ])
+    .unwrap();
+    run(args).unwrap();
+
+    assert!(dir.join("work/Work/Plan.md").exists());
+    assert!(dir.join("personal/Personal/Diary.md").exists());
+    assert!(dir.join("vault/C.md").exists());
+    assert!(!dir.join("vault/Work").exists());
+}
+
+#[test]
+fn test_invalid_replacement() {
+    let dir = test_dir("invalid-replacement");
+    write_file(&dir.join("pages/A.md"), "- a");
+    let config = dir.join("config.json");
+    write_file(
+        &config,
+        r##"{ "replacements": [["#flash(card", "#card"]] }"##,
+    );
+
+    let args = Args::parse_with_config([
+        Path::new("logseq2obsdn"),
+        &dir.join("pages"),
+        &dir.join("vault"),
+        Path::new("--config"),
+        &config,
+    ])
+    .unwrap();
+    let e = run(args).unwrap_err();
+    assert_eq!(e.to_string(), "Invalid replacement pattern '#flash(card'");
+    assert!(!dir.join("vault/A.md").exists());
+}
+
+#[test]
+fn test_missing_paths() {
+    let dir = test_dir("missing-paths");
+    write_file(&dir.join("pages/A.md"), "- a");
+
+    let error = |args: &[&Path]| {
+        let args = std::iter::once(Path::new("logseq2obsdn")).chain(args.iter().copied());
+        Args::parse_with_config(args).err().unwrap().to_string()
+    };
+    let e = error(&[&dir.join("pages")]);
+    assert!(
+        e.starts_with("Missing the obsidian vault to write '"),
+        "{e}"
+    );
+    assert!(e.contains("--convert-all"), "{e}");
+    assert!(e.contains("<LOGSEQ_DIR_OR_PAGE> <VAULT>"), "{e}");
+    let e = error(&[Path::new("--strict"), &dir.join("pages/A.md")]);
+    assert!(e.contains("--extract-ids"), "{e}");
+    assert!(error(&[&dir.join("nope")]).contains("does not exist"));
+    assert!(error(&[]).starts_with("Missing the logseq pages to convert"));
+}
+
+#[test]
+fn test_title_override() {
+    let dir = test_dir("title-override");
+    write_file(&dir.join("pages/Foo.md"), "title:: Foo\n\n- foo");
+    std::fs::create_dir_all(dir.join("vault")).unwrap();
+
+    let args = |input: &Path| {
+        Args::parse_from([
+            Path::new("logseq2obsdn"),
+            input,
+            &dir.join("vault"),
+            Path::new("--title"),
+            Path::new("Bar"),
+        ])
+    };
+    run(args(&dir.join("pages/Foo.md"))).unwrap();
+    assert!(dir.join("vault/Bar.md").exists());
+    assert!(!dir.join("vault/Foo.md").exists());
+
+    assert!(run(args(&dir.join("pages"))).is_err());
+}
+
+#[test]
+fn test_skip_non_utf8() {
+    let dir = test_dir("skip-non-utf8");
+    write_file(&dir.join("pages/Foo.md"), "- foo");
+    std::fs::write(dir.join("pages/Bad.md"), b"- caf\xe9").unwrap();
+
+    let args = |input: &Path| {
+        Args::parse_from([
+            Path::new("logseq2obsdn"),
+            input,
+            &dir.join("vault"),
+            Path::new("--convert-all"),
+            Path::new("--extract-ids"),
+        ])
+    };
+    run(args(&dir.join("pages"))).unwrap();
+    assert!(dir.join("vault/Foo.md").exists());
+    assert!(!dir.join("vault/Bad.md").exists());
+
+    let single = Args::parse_from([
+        Path::new("logseq2obsdn"),
+        &dir.join("pages/Bad.md"),
+        &dir.join("vault"),
+    ]);
+    assert!(run(single).is_err());
+}
+
+#[test]
+fn test_exclude() {
+    let dir = test_dir("exclude");
+    let id = "6511c1e5-1111-4fd6-9c22-0b8f2ba6a111";
+    write_file(
+        &dir.join("pages/templates/Meeting.md"),
+        &format!("- agenda\n  id:: {id}"),
+    );
+    write_file(&dir.join("pages/Foo.md"), "- foo");
+    write_file(&dir.join("pages/Bar.md"), "- bar");
+    std::fs::create_dir_all(dir.join("vault")).unwrap();
+    let ids_file = dir.join("ids.json");
+
+    let args = |filter: &[&str]| {
+        let mut args = vec![
+            "logseq2obsdn".into(),
+            dir.join("pages"),
+            dir.join("vault"),
+            "--convert-all".into(),
+            "--extract-ids".into(),
+            "--force".into(),
+        ];
+        args.extend(filter.iter().map(PathBuf::from));
+        Args::parse_from(args)
+    };
+    run(args(&["--exclude", "templates/**"])).unwrap();
+
+    let vault = dir.join("vault");
+    assert!(vault.join("Foo.md").exists());
+    assert!(!vault.join("Meeting.md").exists());
+    assert!(!read_refs(&ids_file).refs.contains_key(id));
+
+    std::fs::remove_dir_all(&vault).unwrap();
+    run(args(&["--include", "F*", "--exclude", "templates"])).unwrap();
+    assert!(vault.join("Foo.md").exists());
+    assert!(!vault.join("Bar.md").exists());
+    assert!(!vault.join("Meeting.md").exists());
+}
+
+#[test]
+fn test_extract_ids_org() {
+    let dir = test_dir("extract-ids-org");
+    let id = "6511c1e5-1111-4fd6-9c22-0b8f2ba6a111";
+    write_file(
+        &dir.join("pages/Foo.org"),
+        &format!("* foo\n:PROPERTIES:\n:ID: {id}\n:END:"),
+    );
+    let ids_file = dir.join("ids.json");
+
+    let args = Args::parse_from([
+        Path::new("logseq2obsdn"),
+        &dir.join("pages"),
+        &dir.join("vault"),
+        Path::new("--extract-ids"),
+        Path::new("--ids-file"),
+        &ids_file,
+    ]);
+    run(args).unwrap();
+
+    assert_eq!(read_refs(&ids_file).refs[id].file, "Foo");
+}
+
+#[test]
+fn test_vault_checks() {
+    let dir = test_dir("vault-checks");
+    write_file(&dir.join("pages/Foo.md"), "- foo");
+    write_file(&dir.join("file"), "");
+
+    let args =
+        |vault: &Path| Args::parse_from([Path::new("logseq2obsdn"), &dir.join("pages"), vault]);
+    run(args(&dir.join("new/vault"))).unwrap();
+    assert!(dir.join("new/vault/Foo.md").exists());
+
+    let e = run(args(&dir.join("file"))).unwrap_err();
+    assert!(e.to_string().contains("is not a directory"), "{e}");
+
+    let missing = Args::parse_from([
+        Path::new("logseq2obsdn"),
+        &dir.join("missing"),
+        &dir.join("vault"),
+    ]);
+    assert!(run(missing)
+        .unwrap_err()
+        .to_string()
+        .contains("does not exist"));
+}
+
+#[test]
+fn test_report() {
+    let dir = test_dir("report");
+    let id = "6511c1e5-1111-4fd6-9c22-0b8f2ba6a111";
+    let missing = "6511c1e5-2222-4fd6-9c22-0b8f2ba6a111";
+    write_file(
+        &dir.join("pages/Foo.md"),
+        &format!("- ![a](../assets/x.png)\n  id:: {id}"),
+    );
+    write_file(
+        &dir.join("pages/Bar.md"),
+        &format!("- see (({id})) and (({missing}))"),
+    );
+    write_file(&dir.join("assets/x.png"), "foo");
+    let report_file = dir.join("report.json");
+
+    let args = Args::parse_from([
+        Path::new("logseq2obsdn"),
+        &dir.join("pages"),
+        &dir.join("vault"),
+        Path::new("--convert-all"),
+        Path::new("--report-json"),
+        &report_file,
+    ]);
+    run(args).unwrap();
+
+    let report: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(report_file).unwrap()).unwrap();
+    assert_eq!(report["converted_pages"], 2);
+    assert_eq!(report["copied_assets"], 1);
+    assert_eq!(report["resolved_refs"], 1);
+    assert_eq!(report["unresolved_refs"], 1);
+    assert_eq!(report["failed_files"].as_array().unwrap().len(), 0);
 }
@@ -0,0 +1,84 @@
+use std::collections::BTreeMap;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::Result;
+
+/// Bumped whenever the manifest changes in a way that invalidates old ones.
+const MANIFEST_VERSION: u32 = 1;
+
+/// The pages converted by previous runs, used to skip unchanged pages.
+#[derive(serde::Serialize, serde::Deserialize, Default, Clone)]
+pub struct Manifest {
+    version: u32,
+    pub pages: BTreeMap<PathBuf, Entry>,
+}
+
+/// A converted page.
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub struct Entry {
+    /// The modification time of the logseq page when it was converted.
+    pub modified: SystemTime,
+    /// The converted page.
+    pub output: PathBuf,
+    /// The assets of the page as `(source, destination)`.
+    pub assets: Vec<(PathBuf, PathBuf)>,
+    /// The logseq ids of the blocks of the page.
+    #[serde(default)]
+    pub ids: Vec<String>,
+    /// The logseq ids of the blocks whose anchor was written.
+    #[serde(default)]
+    pub anchors: Vec<String>,
+    /// A hash of the options the page was converted with.
+    #[serde(default)]
+    pub options: u64,
+    /// The logseq ids referenced by the page.
+    #[serde(default)]
+    pub refs: Vec<String>,
+    /// A hash of what `refs`, the aliases and journal dates resolved to.
+    #[serde(default)]
+    pub refs_hash: u64,
+}
+
+impl Manifest {
+    /// Loads the manifest at `path`, missing, unreadable or outdated manifests
+    /// are empty.
+    pub fn load(path: &Path) -> Manifest {
+        std::fs::File::open(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|f| Ok(serde_json::from_reader::<_, Manifest>(BufReader::new(f))?))
+            .ok()
+            .filter(|m| m.version == MANIFEST_VERSION)
+            .unwrap_or_default()
+    }
+
+    pub fn save(&mut self, path: &Path) -> Result<()> {
+        self.version = MANIFEST_VERSION;
+        let w = BufWriter::new(std::fs::File::create(path)?);
+        serde_json::to_writer_pretty(w, self)?;
+        Ok(())
+    }
+
+    /// Returns the entry of `page` if it wasn't modified since it was converted
+    /// and its output still exists.
+    pub fn unchanged(&self, page: &Path) -> Option<&Entry> {
+        let entry = self.pages.get(page)?;
+        let modified = std::fs::metadata(page).and_then(|m| m.modified()).ok()?;
+        (modified == entry.modified && entry.output.exists()).then_some(entry)
+    }
+
+    /// Removes and returns the entries whose logseq page doesn't exist anymore.
+    pub fn remove_deleted(&mut self) -> Vec<(PathBuf, Entry)> {
+        let deleted = self
+            .pages
+            .keys()
+            .filter(|p| !p.exists())
+            .cloned()
+            .collect::<Vec<_>>();
+        deleted
+            .into_iter()
+            .filter_map(|p| self.pages.remove_entry(&p))
+            .collect()
+    }
+}
@@ -1,58 +1,434 @@
-use std::collections::hash_map::DefaultHasher;
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
 use std::ffi::OsStr;
-use std::hash::{Hash, Hasher};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
 use itertools::Itertools;
 use once_cell::sync::OnceCell;
 use regex::Regex;
 use strum::IntoEnumIterator;
 
-#[derive(serde::Serialize, serde::Deserialize, Default)]
+use crate::postprocess::PostProcess;
+
+/// The extracted ids, sorted so that the written file is stable across runs.
+#[derive(serde::Serialize, serde::Deserialize, Default, Clone)]
 pub struct RefsFile {
-    pub refs: HashMap<String, Ref>,
+    pub refs: BTreeMap<String, Ref>,
+    /// The text of the blocks, only stored for inlining block references.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub texts: BTreeMap<String, String>,
+    /// The titles of the pages by their lowercase aliases.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub aliases: BTreeMap<String, String>,
 }
 
-#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(serde::Serialize, serde::Deserialize, Clone, PartialEq, Eq, Debug)]
 pub struct Ref {
     pub file: String,
     pub id: String,
 }
 
 impl Ref {
-    fn get_link(&self, curr_title: &str) -> String {
-        if self.file == curr_title.trim() {
-            self.id.clone()
+    /// The link target of the ref from the page `curr_title`, e.g. `Page#^anchor`.
+    pub fn get_link(&self, curr_title: &str) -> String {
+        // Block anchors are only links with a `#` in front, `register_id`
+        // usually stores them like that already.
+        let id = match self.id.starts_with('^') {
+            true => format!("#{}", self.id),
+            false => self.id.clone(),
+        };
+        // References to a page itself have no anchor.
+        if self.file == curr_title.trim() && !id.is_empty() {
+            id
         } else {
-            format!("{}{}", self.file, self.id)
+            format!("{}{id}", self.file)
         }
     }
 }
 
+#[test]
+fn test_get_link() {
+    let r = |id: &str| Ref {
+        file: "Page".to_owned(),
+        id: id.to_owned(),
+    };
+    assert_eq!(r("#Header").get_link("Page"), "#Header");
+    assert_eq!(r("#^abc").get_link("Page"), "#^abc");
+    assert_eq!(r("^abc").get_link("Page"), "#^abc");
+    assert_eq!(r("#^abc").get_link("Other"), "Page#^abc");
+    assert_eq!(r("^abc").get_link("Other"), "Page#^abc");
+    assert_eq!(r("").get_link("Page"), "Page");
+}
+
 pub trait Data {
     fn page_title(&mut self, title: &str);
-    fn copy_asset(&mut self, path: &str) -> String;
+    /// Copies the asset `path` of the current page into the vault and returns
+    /// the link to it.
+    ///
+    /// `path` is the path as written in the logseq page, relative to the page's
+    /// directory (usually `../assets/<name>`), or an absolute path or `file://`
+    /// url. The returned link is used as is,
+    /// so it decides the name of the asset in the vault, see
+    /// [`crate::assets::hashed_file_name`] for content-addressed names.
+    fn copy_asset(&mut self, path: &str) -> Result<String>;
+    /// The assets [`Data::copy_asset`] was called for so far as `(source,
+    /// destination)`, for implementations that copy them afterwards, see
+    /// [`crate::assets::execute_asset_copies`].
+    fn asset_plan(&self) -> &[(PathBuf, PathBuf)] {
+        &[]
+    }
     fn register_id(&mut self, id: &Id);
     fn query_id(&self, logseq_id: &str) -> Option<&Ref>;
     fn curr_title(&self) -> &str;
+
+    /// Returns the text of the block with the id `logseq_id`, if known.
+    fn query_block_text(&self, _logseq_id: &str) -> Option<&str> {
+        None
+    }
+
+    /// Called for every block reference that was converted.
+    fn resolved_ref(&mut self, _logseq_id: &str) {}
+
+    /// Called for every block id a parsed page refers to or embeds.
+    fn referenced_id(&mut self, _logseq_id: &str) {}
+
+    /// Whether the anchor of the block `logseq_id` is written, implementations
+    /// that know all references can leave out the anchors of unreferenced blocks.
+    fn is_referenced(&self, _logseq_id: &str) -> bool {
+        true
+    }
+
+    /// Called for a block reference whose id isn't known, the reference is left as is.
+    fn unresolved_ref(&mut self, logseq_id: &str) {
+        let message = format!(
+            "Unresolved block reference (({logseq_id})) in '{}'",
+            self.curr_title()
+        );
+        self.warn(&message);
+    }
+
+    /// Whether [`Data::trace`] is called for the conversion passes.
+    fn tracing(&self) -> bool {
+        false
+    }
+
+    /// Called with the text of a block before and after every conversion pass
+    /// that changed it, if [`Data::tracing`].
+    fn trace(&mut self, _pass: &str, _before: &str, _after: &str) {}
+
+    /// Reports a problem that doesn't stop the conversion.
+    fn warn(&mut self, message: &str) {
+        eprintln!("Warning: {message}");
+    }
+
+    /// Returns the page that links to the journal `page` (e.g. `Oct 5th, 2023`)
+    /// should point to, `None` if `page` isn't a journal.
+    fn journal_link(&self, _page: &str) -> Option<String> {
+        None
+    }
+
+    /// Returns the link target of links to the page `page`, if it isn't the
+    /// page itself.
+    fn page_link(&self, _page: &str) -> Option<String> {
+        None
+    }
+
+    /// Called for every alias of the current page.
+    fn register_alias(&mut self, _alias: &str) {}
+
+    /// Returns the title of the page with the alias `alias`, if known.
+    fn query_alias(&self, _alias: &str) -> Option<&str> {
+        None
+    }
+
+    /// The options of the conversion.
+    fn config(&self) -> &Config {
+        static DEFAULT: OnceCell<Config> = OnceCell::new();
+        DEFAULT.get_or_init(Config::default)
+    }
+}
+
+/// The options of the conversion, see the CLI options of the same name.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub callout_style: CalloutStyle,
+    pub cloze_style: ClozeStyle,
+    pub block_props: BlockProps,
+    pub anchor_style: AnchorStyle,
+    /// Whether `SCHEDULED`/`DEADLINE` dates are converted to Obsidian Tasks dates,
+    /// otherwise they are removed.
+    pub task_dates: bool,
+    /// Whether `DOING`/`NOW` tasks become `[/]` and `WAITING` tasks `[?]`
+    /// checkboxes, instead of `[ ]`.
+    pub rich_task_states: bool,
+    /// Whether the text of done and canceled tasks is struck through.
+    pub strike_done: bool,
+    /// Whether standalone block references are replaced by the text of the block
+    /// instead of a link, if the text is known.
+    pub inline_refs: bool,
+    /// Whether the original logseq ids of blocks are kept as `%% logseq-id: ... %%`
+    /// comments.
+    pub keep_logseq_ids: bool,
+    /// Whether queries are removed instead of being commented out.
+    pub drop_queries: bool,
+    /// Whether `{{renderer ...}}` macros are removed instead of being commented out.
+    pub drop_renderers: bool,
+    /// Whether macros whose content logseq generates, like `{{namespace}}`, are
+    /// removed instead of being commented out.
+    pub drop_dynamic: bool,
+    /// Whether the lines of paragraph blocks end with a hard line break (`\`).
+    pub hard_breaks: bool,
+    /// Whether blocks starting with bold text or a heading stay list items,
+    /// instead of becoming normal paragraphs.
+    pub keep_bold_headers_as_list: bool,
+    /// Whether images are converted to `![[path]]` embeds instead of markdown images.
+    pub image_embeds: bool,
+    /// Whether image captions are kept as an italic line beneath the image
+    /// instead of becoming its alt text.
+    pub captions_below: bool,
+    /// Whether an italic line directly below an image is taken as its caption.
+    pub italic_captions: bool,
+    /// Whether `#[[tag]]`s are converted to `[[tag]]` links instead of `#tag`s.
+    pub tags_as_links: bool,
+    /// Whether the children of empty blocks take their place, instead of
+    /// staying under an empty bullet.
+    pub flatten_empty_groups: bool,
+    /// Whether highlights with a `[[$color]]` become `<mark>` elements of that
+    /// color instead of plain `==highlights==`.
+    pub html_highlights: bool,
+    /// Whether the `#tag`s of the blocks are added to the tags of the page, those
+    /// at the end of a block are removed from it.
+    pub hoist_block_tags: bool,
+    /// Whether the page starts with its title as a `# heading`, unless its first
+    /// block already is that heading.
+    pub title_heading: bool,
+    /// Whether `public:: true` pages get a `publish: true` frontmatter key, the
+    /// property is dropped otherwise.
+    pub public_as_publish: bool,
+    /// User-defined `(pattern, replacement)` rules applied to the text of every
+    /// block after the built-in passes, `$1` etc. in the replacement expand to
+    /// the groups of the pattern.
+    pub replacements: Vec<(Regex, String)>,
+    /// The deepest level of nesting, deeper blocks are moved up to it.
+    pub max_depth: usize,
+    /// The passes run in order at the end of [`Page::transform`].
+    pub post_processors: Vec<Arc<dyn PostProcess>>,
+    /// The vault directory assets are copied to, next to the page if `None`.
+    ///
+    /// This and the following options are only read by [`Data`]
+    /// implementations, see [`Data::copy_asset`].
+    pub attachments_dir: Option<PathBuf>,
+    /// Whether assets keep their folders below `assets` in `attachments_dir`.
+    pub preserve_asset_tree: bool,
+    /// The `assets` folder of the graph, assets that can't be found by their
+    /// path are looked up in it by their file name.
+    pub assets_dir: Option<PathBuf>,
+    /// Whether namespaced pages (`A/B/C`) are written into nested folders.
+    pub namespaces: bool,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            callout_style: CalloutStyle::Admonition,
+            cloze_style: ClozeStyle::Highlight,
+            block_props: BlockProps::Dataview,
+            anchor_style: AnchorStyle::Hash,
+            task_dates: true,
+            rich_task_states: false,
+            strike_done: false,
+            inline_refs: false,
+            keep_logseq_ids: false,
+            drop_queries: false,
+            drop_renderers: false,
+            drop_dynamic: false,
+            hard_breaks: false,
+            keep_bold_headers_as_list: false,
+            image_embeds: false,
+            captions_below: false,
+            italic_captions: false,
+            tags_as_links: false,
+            flatten_empty_groups: false,
+            html_highlights: false,
+            public_as_publish: false,
+            title_heading: false,
+            hoist_block_tags: false,
+            max_depth: 12,
+            replacements: vec![],
+            post_processors: vec![],
+            attachments_dir: None,
+            preserve_asset_tree: false,
+            assets_dir: None,
+            namespaces: false,
+        }
+    }
+}
+
+/// How self-border blocks are rendered.
+#[derive(
+    strum::EnumString,
+    strum::Display,
+    serde::Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Default,
+)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum CalloutStyle {
+    /// An `ad-def` block of the Admonition plugin.
+    #[default]
+    Admonition,
+    /// A built-in `[!note]` callout.
+    Native,
 }
 
 #[derive(Debug)]
 pub struct Page {
     pub title: String,
     pub alias: Vec<String>,
+    pub tags: Vec<String>,
+    /// All other page properties in order of appearance, emitted as frontmatter.
+    pub props: Vec<(String, Vec<String>)>,
+    /// The entries of the YAML frontmatter the page already had as `(key, entry)`,
+    /// they replace the properties with the same key.
+    pub frontmatter: Vec<(String, String)>,
     pub blocks: Vec<Block>,
 }
 
+/// How `{{cloze answer}}`s are rendered for the Spaced Repetition plugin.
+#[derive(
+    strum::EnumString,
+    strum::Display,
+    serde::Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Default,
+)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ClozeStyle {
+    /// `==answer==`
+    #[default]
+    Highlight,
+    /// `{{answer}}`
+    Braces,
+}
+
+/// How the anchors of referenced blocks are named.
+#[derive(
+    strum::EnumString,
+    strum::Display,
+    serde::Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Default,
+)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum AnchorStyle {
+    /// A hash of the block's text (`^71319177cfda2d71`).
+    #[default]
+    Hash,
+    /// The first words of the block's text (`^some-block`).
+    Slug,
+}
+
+/// How block properties other than the ones logseq uses internally are converted.
+#[derive(
+    strum::EnumString,
+    strum::Display,
+    serde::Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Default,
+)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum BlockProps {
+    /// Kept as `key:: value` lines, which are Dataview inline fields.
+    #[default]
+    Dataview,
+    /// Removed.
+    Drop,
+}
+
 #[derive(Debug)]
 pub struct Id {
     pub logseq_id: String,
     pub obsdn_id: String,
+    /// The text of the block.
+    pub text: String,
+    /// The title of the page of the block, see [`Data::curr_title`].
+    pub page: String,
 }
 
-#[derive(Debug)]
+impl Id {
+    /// The first line of the text of the block.
+    pub fn first_line(&self) -> &str {
+        self.text.lines().next().unwrap_or_default()
+    }
+}
+
+#[test]
+fn test_registered_ids() {
+    #[derive(Default)]
+    struct Index {
+        title: String,
+        ids: Vec<(String, String, String)>,
+    }
+    impl Data for Index {
+        fn page_title(&mut self, title: &str) {
+            self.title = title.to_owned();
+        }
+        fn copy_asset(&mut self, path: &str) -> Result<String> {
+            Ok(path.to_owned())
+        }
+        fn register_id(&mut self, id: &Id) {
+            let first_line = id.first_line().to_owned();
+            self.ids
+                .push((id.page.clone(), id.logseq_id.clone(), first_line));
+        }
+        fn query_id(&self, _logseq_id: &str) -> Option<&Ref> {
+            None
+        }
+        fn curr_title(&self) -> &str {
+            &self.title
+        }
+    }
+
+    let text = "title:: Notes\nid:: 6511c1e5-0000-4fd6-9c22-0b8f2ba6a111\n\n- first line\n  second line\n  id:: 6511c1e5-1111-4fd6-9c22-0b8f2ba6a111";
+    let mut index = Index::default();
+    Page::parse(Path::new("notes.md"), text, &mut index).unwrap();
+    let id = |page: &str, id: &str, line: &str| (page.to_owned(), id.to_owned(), line.to_owned());
+    assert_eq!(
+        index.ids,
+        [
+            id("Notes", "6511c1e5-0000-4fd6-9c22-0b8f2ba6a111", "[[Notes]]"),
+            id(
+                "Notes",
+                "6511c1e5-1111-4fd6-9c22-0b8f2ba6a111",
+                "first line"
+            ),
+        ]
+    );
+}
+
+#[derive(Debug, Default)]
 pub struct Block {
     pub text: String,
     pub id: Option<Id>,
@@ -60,6 +436,15 @@ pub struct Block {
     pub children: Vec<Block>,
     pub is_list_item: bool,
     pub self_border: bool,
+    /// Whether the block is folded in logseq (`collapsed:: true`).
+    pub collapsed: bool,
+    /// Whether the block is an item of a numbered list
+    /// (`logseq.order-list-type:: number`).
+    pub ordered: bool,
+    /// The date a `DONE` task was completed, taken from its `:LOGBOOK:`.
+    pub completed: Option<String>,
+    /// The callout type of a block colored with `background-color::`.
+    pub callout: Option<String>,
 }
 
 fn self_border_re() -> &'static Regex {
@@ -67,29 +452,135 @@ fn self_border_re() -> &'static Regex {
     SELF_BORDER_RE.get_or_init(|| Regex::new(r"( )?#\.v-self-border").unwrap())
 }
 
+fn footnote_def_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| Regex::new(r"^(?:- )?\[\^[^\]]+\]:").unwrap())
+}
+
+/// Matches the delimiter row of a markdown table (`|---|:--:|`).
+fn table_delimiter_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| Regex::new(r"^\|?\s*:?-+:?\s*(?:\|\s*:?-+:?\s*)*\|?$").unwrap())
+}
+
+/// Whether `text` (optionally a list item) is a markdown table, a header row
+/// followed by a delimiter row and any number of rows.
+fn is_table(text: &str) -> bool {
+    let mut lines = text
+        .strip_prefix("- ")
+        .unwrap_or(text)
+        .lines()
+        .map(str::trim);
+    let is_row = |l: &str| l.starts_with('|') || l.ends_with('|');
+    match (lines.next(), lines.next()) {
+        (Some(header), Some(delimiter)) => {
+            is_row(header)
+                && delimiter.contains('-')
+                && table_delimiter_re().is_match(delimiter)
+                && lines.all(is_row)
+        }
+        _ => false,
+    }
+}
+
+/// Matches a block that is only a horizontal rule.
+fn rule_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| Regex::new(r"^(?:- )?-{3,}\s*$").unwrap())
+}
+
+/// Groups:
+/// 1: inline math of `\(...\)`
+/// 2: display math of `\[...\]`
+fn latex_math_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| Regex::new(r"\\\(([^\n]+?)\\\)|(?s)\\\[(.+?)\\\]").unwrap())
+}
+
 fn only_math_re() -> &'static Regex {
     static RE: OnceCell<Regex> = OnceCell::new();
     RE.get_or_init(|| Regex::new(r"(?s)^\s*(?:- )?\${2}.*\${2}\s*$").unwrap())
 }
 
+/// Groups:
+/// 0: whole
+/// 1: name
+/// 2: path
+/// 3: annotation (`{:height 200, :width 400}`)
 fn image_re() -> &'static Regex {
     static RE: OnceCell<Regex> = OnceCell::new();
-    RE.get_or_init(|| Regex::new(r"(?s)!\[([^\]]*)\]\(([^\)]*)\)").unwrap())
+    RE.get_or_init(|| Regex::new(r"(?s)!\[([^\]]*)\]\(([^\)]*)\)(\{[^\}]*\})?").unwrap())
+}
+
+/// Groups:
+/// 0: whole
+/// 1: path of the draw, relative to the graph
+fn draw_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| {
+        Regex::new(r"!?\[{2}((?:\.\./)?draws/[^\]\n]+\.excalidraw(?:\.md)?)\]{2}").unwrap()
+    })
+}
+
+/// Whether `path` is an Excalidraw drawing.
+fn is_draw(path: &str) -> bool {
+    path.ends_with(".excalidraw") || path.ends_with(".excalidraw.md")
+}
+
+/// Groups:
+/// 1: embedded page or file
+fn only_embed_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| Regex::new(r"(?s)^\s*-?\s*!\[{2}([^\]]+)\]{2}\s*$").unwrap())
 }
 
 fn only_image_re() -> &'static Regex {
     static RE: OnceCell<Regex> = OnceCell::new();
-    RE.get_or_init(|| Regex::new(r"(?s)^\s*-?\s*!\[([^\]]*)\]\(([^\)]*)\)\s*$").unwrap())
+    RE.get_or_init(|| {
+        Regex::new(
+            r"(?s)^\s*-?\s*!\[([^\]]*)\]\(([^\)]*)\)(\{[^\}]*\})?(?:\n\s*[_*][^_*\n]+[_*])?\s*$",
+        )
+        .unwrap()
+    })
+}
+
+/// Groups:
+/// 1: caption
+fn image_caption_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| Regex::new(r#":caption\s+"([^"]*)""#).unwrap())
+}
+
+/// An italic line directly after an image.
+///
+/// Groups:
+/// 0: whole, including the preceding newline
+/// 1: caption in `_..._`
+/// 2: caption in `*...*`
+fn caption_line_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| Regex::new(r"^\n[ \t]*(?:_([^_\n]+)_|\*([^*\n]+)\*)[ \t]*(?:$|\n)").unwrap())
 }
 
+/// Groups:
+/// 1: width
+fn image_width_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| Regex::new(r":width\s+(\d+)").unwrap())
+}
+
+/// Groups:
+/// 1: `#`s of the heading level
+/// 2: heading
 fn header_re() -> &'static Regex {
     static RE: OnceCell<Regex> = OnceCell::new();
-    RE.get_or_init(|| Regex::new(r"^\s*-?\s*#+\s(.*[^\s])\s*$").unwrap())
+    RE.get_or_init(|| Regex::new(r"^\s*-?\s*(#{1,6})\s(.*[^\s])\s*$").unwrap())
 }
 
+/// Matches the characters that can't be part of an Obsidian heading link.
 fn header_san_re() -> &'static Regex {
     static RE: OnceCell<Regex> = OnceCell::new();
-    RE.get_or_init(|| Regex::new(r"[^a-zA-Z0-9\-_öäüÖÄÜèàé]+").unwrap())
+    RE.get_or_init(|| Regex::new(r"(?:\s*[#\^|\[\]:])+\s*").unwrap())
 }
 
 /// Groups:
@@ -101,6 +592,42 @@ fn file_link_re() -> &'static Regex {
     RE.get_or_init(|| Regex::new(r"\[([^\]]*)\]\(\[{2}([^\]]+)\]{2}\)").unwrap())
 }
 
+/// Groups:
+/// 0: whole
+/// 1: indentation
+/// 2: kind (`QUOTE`, `NOTE`, ...)
+/// 3: content
+/// 4: kind of the end
+fn org_block_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| {
+        Regex::new(r"(?ms)^([ \t]*)#\+BEGIN_(\w+)[^\n]*\n(.*?)\n?^[ \t]*#\+END_(\w+)[ \t]*$")
+            .unwrap()
+    })
+}
+
+/// An `id::` property at the end of a line of text.
+///
+/// Groups:
+/// 1: logseq id
+fn trailing_id_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| {
+        Regex::new(r"\s+id::\s*([0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12})\s*$")
+            .unwrap()
+    })
+}
+
+/// Groups:
+/// 1: id
+fn block_ref_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| {
+        Regex::new(r"\({2}([0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12})\){2}")
+            .unwrap()
+    })
+}
+
 /// Groups:
 /// 1: url
 /// 2: id
@@ -108,7 +635,7 @@ fn standalone_id_re() -> &'static Regex {
     static RE: OnceCell<Regex> = OnceCell::new();
     RE.get_or_init(|| {
         Regex::new(
-            r"[^\(](\({2}([0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12})\){2})",
+            r"(?:^|[^\(])(\({2}([0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12})\){2})",
         )
         .unwrap()
     })
@@ -126,452 +653,3641 @@ fn link_id_re() -> &'static Regex {
 /// Groups:
 /// 0: whole
 /// 1: id
+/// 2: display annotations like `width=300` (optional)
 fn embed_id_re() -> &'static Regex {
     static RE: OnceCell<Regex> = OnceCell::new();
-    RE.get_or_init(|| Regex::new(r"\{\{embed \({2}([0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12})\){2}\}\}").unwrap())
+    RE.get_or_init(|| Regex::new(r"\{\{embed \({2}([0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12})\){2}(?:\s+([^{}]*?))?\s*\}\}").unwrap())
 }
 
-impl Block {
-    fn transform(
-        &mut self,
-        parent: Option<&Block>,
-        prev_sibling: Option<&Block>,
-        data: &mut dyn Data,
-    ) {
-        let mut children = std::mem::replace(&mut self.children, Vec::new());
-        for i in 0..children.len() {
-            let (prev, rest) = children.as_mut_slice().split_at_mut(i);
-            let (curr, _rest) = rest.split_at_mut(1);
-            let curr = curr.first_mut().unwrap();
-            curr.transform(Some(self), prev.last(), data);
-        }
-        self.children = children;
-
-        let parent_none_or_normal = parent.map(|p| !p.is_list_item).unwrap_or(true);
-        let prev_none_or_normal = prev_sibling.map(|p| !p.is_list_item).unwrap_or(true);
+/// Groups:
+/// 1: width in pixels
+fn embed_width_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| Regex::new(r"^:?(?:width\s*[=:]?\s*)?(\d+)(?:px)?$").unwrap())
+}
 
-        if parent_none_or_normal && only_math_re().is_match(&self.text) {
-            self.set_list_item(false);
-        }
+/// Groups:
+/// 0: whole
+/// 1: macro name
+/// 2: arguments, which may contain one level of braces
+fn macro_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| {
+        Regex::new(r"\{\{([a-zA-Z][\w-]*)(?:\s+((?:[^{}]|\{[^{}]*\})*?))?\s*\}\}").unwrap()
+    })
+}
 
-        if parent.is_none() && self.text.starts_with("- ## ") {
-            self.text.remove(2);
-        }
-        if parent.is_none() && self.text.starts_with("## ") {
-            self.text.remove(0);
-        }
+/// Converts the arguments of a logseq macro, without surrounding `[[ ]]`.
+type MacroHandler = fn(&str, &Config) -> String;
 
-        if parent_none_or_normal && prev_none_or_normal && only_image_re().is_match(&self.text) {
-            self.set_list_item(false);
-        }
+/// The logseq macros converted by name, the macros in [`OWN_PASS_MACROS`] are
+/// converted by their own pass and all others become comments.
+const MACROS: &[(&str, MacroHandler)] = &[
+    ("video", video_macro),
+    ("youtube", |url, _| format!("<{url}> ![]({url})")),
+    ("tweet", |url, _| format!("[Tweet]({url})")),
+    ("twitter", |url, _| format!("[Tweet]({url})")),
+    ("bilibili", |id, _| match id.contains("://") {
+        true => format!("[Bilibili]({id})"),
+        false => format!("[Bilibili](https://www.bilibili.com/video/{id})"),
+    }),
+    ("namespace", |page, c| {
+        dynamic_macro(&format!("namespace {page}"), c)
+    }),
+    ("page-tags", |_, c| dynamic_macro("page-tags", c)),
+    ("function", |f, c| {
+        dynamic_macro(&format!("function {f}"), c)
+    }),
+    ("sum", |args, c| calc_macro("sum", args, c)),
+    ("count", |args, c| calc_macro("count", args, c)),
+];
 
-        let mut text = self.text.clone();
-        for m in image_re()
-            .captures_iter(&self.text)
-            .collect_vec()
-            .into_iter()
-            .rev()
-        {
-            let (_name, path) = match (m.get(1), m.get(2)) {
-                (Some(n), Some(p)) => (n, p),
-                _ => continue,
-            };
+const OWN_PASS_MACROS: &[&str] = &["embed", "cloze", "query", "renderer"];
 
-            let new_path = data.copy_asset(path.as_str());
-            text.replace_range(path.range(), &new_path);
-        }
+/// Content that logseq generates, kept as a comment for rebuilding it with
+/// Dataview.
+fn dynamic_macro(call: &str, config: &Config) -> String {
+    match config.drop_dynamic {
+        true => String::new(),
+        false => format!("%% logseq dynamic: {} %%", call.trim()),
+    }
+}
 
-        self.text = text.clone();
-        for m in file_link_re()
-            .captures_iter(&self.text)
-            .collect_vec()
-            .into_iter()
-            .rev()
-        {
-            let (whole, title, url) = match (m.get(0), m.get(1), m.get(2)) {
-                (Some(a), Some(b), Some(c)) => (a, b, c),
-                _ => continue,
-            };
-            let url = url.as_str();
-            let title = title.as_str();
-            text.replace_range(whole.range(), &format!("[[{url}|{title}]]"));
-        }
+/// `{{sum}}` and `{{count}}` of numbers are calculated, other calculations
+/// are dynamic. Sums have as many decimals as the number with the most.
+fn calc_macro(name: &str, args: &str, config: &Config) -> String {
+    let values = args
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|a| !a.is_empty())
+        .collect_vec();
+    let numbers: Option<Vec<f64>> = values.iter().map(|a| a.parse().ok()).collect();
+    let decimals = values
+        .iter()
+        .map(|a| a.split_once('.').map_or(0, |(_, d)| d.len()))
+        .max()
+        .unwrap_or(0);
+    match numbers.filter(|n| !n.is_empty()) {
+        Some(n) if name == "sum" => format!("{:.decimals$}", n.iter().sum::<f64>()),
+        Some(n) => n.len().to_string(),
+        None => dynamic_macro(&format!("{name} {args}"), config),
+    }
+}
 
-        self.text = text.clone();
-        for m in embed_id_re()
-            .captures_iter(&self.text)
-            .collect_vec()
-            .into_iter()
-            .rev()
-        {
-            let (whole, id) = match (m.get(0), m.get(1)) {
-                (Some(n), Some(p)) => (n, p),
-                _ => continue,
-            };
-            if let Some(r) = data.query_id(id.as_str()) {
-                let link = r.get_link(data.curr_title());
-                text.replace_range(whole.range(), &format!("![[{link}]]"));
-            }
-        }
+fn video_macro(url: &str, _config: &Config) -> String {
+    if url.contains("youtube.com/") || url.contains("youtu.be/") {
+        format!("<{url}> ![]({url})")
+    } else {
+        format!("![]({url})")
+    }
+}
 
-        self.text = text.clone();
-        for m in link_id_re()
-            .captures_iter(&self.text)
-            .collect_vec()
-            .into_iter()
-            .rev()
-        {
-            let (whole, title, id) = match (m.get(0), m.get(1), m.get(2)) {
-                (Some(w), Some(a), Some(b)) => (w, a, b),
-                _ => continue,
-            };
-            if let Some(r) = data.query_id(id.as_str()) {
-                let link = r.get_link(data.curr_title());
-                let title = title.as_str();
-                text.replace_range(whole.range(), &format!("[[{link}|{title}]]"));
-            }
-        }
+/// Groups:
+/// 0: whole
+/// 1: query
+fn query_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| Regex::new(r"\{\{query\s+(.*?)\}\}").unwrap())
+}
 
-        self.text = text.clone();
-        for m in standalone_id_re()
-            .captures_iter(&self.text)
-            .collect_vec()
-            .into_iter()
-            .rev()
-        {
-            let (url, id) = match (m.get(1), m.get(2)) {
-                (Some(n), Some(p)) => (n, p),
-                _ => continue,
-            };
-            if let Some(r) = data.query_id(id.as_str()) {
-                let link = r.get_link(data.curr_title());
-                text.replace_range(url.range(), &format!("[[{link}]]"));
-            }
-        }
-    }
+/// Groups:
+/// 0: whole
+/// 1: arguments, which may contain one level of braces
+fn renderer_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| Regex::new(r"\{\{renderer\s+((?:[^{}]|\{[^{}]*\})*?)\s*\}\}").unwrap())
+}
 
-    pub fn parse(text: &str, data: &mut dyn Data) -> Result<Self> {
-        let mut first_child = None;
-        let mut id = None;
-        let body = text
-            .lines()
-            .chain([""])
-            .tuple_windows::<(&str, &str)>()
-            .find_map(|(l, curr)| {
-                if let Some((Prop::Id, val)) = parse_prop(curr) {
-                    id = Some(val);
-                    return None;
+/// Groups:
+/// 0: whole
+/// 1: query
+fn query_block_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| Regex::new(r"(?s)#\+BEGIN_QUERY\n?(.*?)\n?#\+END_QUERY").unwrap())
+}
+
+fn card_tag_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| Regex::new(r"(?:^|\s)#card\b").unwrap())
+}
+
+/// Groups:
+/// 0: whole
+/// 1: answer
+fn cloze_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| Regex::new(r"\{\{cloze\s+(.*?)\}\}").unwrap())
+}
+
+/// Groups:
+/// 0: whole
+/// 1: page name
+/// 2: `#heading` (optional)
+/// 3: display annotations like `width=300` (optional)
+fn page_embed_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| {
+        Regex::new(r"\{\{embed\s+\[{2}([^\]#]+?)(#[^\]]+)?\]{2}(?:\s+([^{}]*?))?\s*\}\}").unwrap()
+    })
+}
+
+/// Extensions of embedded files that obsidian can size.
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "svg", "webp", "bmp", "avif"];
+
+/// Renders the embed of `target`, only images keep the width of the
+/// `annotation`, obsidian can't size other embeds.
+fn embed(target: &str, annotation: Option<&str>) -> String {
+    let is_image = Path::new(target)
+        .extension()
+        .is_some_and(|e| IMAGE_EXTENSIONS.contains(&e.to_string_lossy().to_lowercase().as_str()));
+    let width = annotation
+        .filter(|_| is_image)
+        .and_then(|a| embed_width_re().captures(a.trim()));
+    match width {
+        Some(width) => format!("![[{target}|{}]]", &width[1]),
+        None => format!("![[{target}]]"),
+    }
+}
+
+/// Groups:
+/// 1: task keyword
+fn task_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| {
+        Regex::new(r"^(?:- )?(TODO|LATER|NOW|DOING|WAITING|DONE|CANCELED|CANCELLED) ").unwrap()
+    })
+}
+
+/// A markdown checkbox that is already written in the page.
+fn checkbox_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| Regex::new(r"^- \[[ xX/?\-]\] ").unwrap())
+}
+
+/// Groups:
+/// 1: `[#A]` token, including the following space
+/// 2: priority
+fn task_priority_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| Regex::new(r"^- \[.\] (\[#([ABC])\] ?)").unwrap())
+}
+
+/// Groups:
+/// 1: date of a `CLOCK:` or `:COMPLETED:` timestamp
+fn logbook_date_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| Regex::new(r"\[(\d{4}-\d{2}-\d{2})[^\]]*\]").unwrap())
+}
+
+fn task_dates_line_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| Regex::new(r"^\s*(?:(?:SCHEDULED|DEADLINE): <[^>]*>\s*)+$").unwrap())
+}
+
+/// Groups:
+/// 1: kind
+/// 2: date
+fn task_date_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| Regex::new(r"(SCHEDULED|DEADLINE): <(\d{4}-\d{2}-\d{2})[^>]*>").unwrap())
+}
+
+/// Groups:
+/// 1: key
+/// 2: value
+fn any_prop_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| Regex::new(r"^([\w\-\.]+)::(?:\s+(.*))?$").unwrap())
+}
+
+/// Groups:
+/// 0: whole
+/// 1: tag
+fn hashtag_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| Regex::new(r"#\[{2}([^\]]+)\]{2}").unwrap())
+}
+
+/// Groups:
+/// 1: tag of an obsidian `#tag`, which isn't only a number
+fn block_tag_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| Regex::new(r"(?:^|\s)#([\w/\-]*[^\W\d][\w/\-]*)").unwrap())
+}
+
+/// Matches the `#tag`s at the end of a block.
+fn trailing_tags_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| Regex::new(r"(?:\s+#[\w/\-]*[^\W\d][\w/\-]*)+\s*$").unwrap())
+}
+
+/// Groups:
+/// 0: whole
+/// 1: page
+fn page_link_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| Regex::new(r"\[{2}([^\[\]]+)\]{2}").unwrap())
+}
+
+/// Groups:
+/// 1: highlighted text
+fn highlight_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| Regex::new(r"\^\^([^\^\n]+)\^\^").unwrap())
+}
+
+/// A highlight with a `[[$color]]` in front.
+///
+/// Groups:
+/// 1: color
+/// 2: text of a `^^text^^` highlight
+/// 3: text of a `==text==` highlight
+fn colored_highlight_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| Regex::new(r"\[\[\$(\w+)\]\](?:\^\^([^\^\n]+)\^\^|==([^=\n]+)==)").unwrap())
+}
+
+/// Matches what the transform passes must leave alone: fenced code blocks,
+/// inline code spans, `$$` and `$` math, and the `](url)` part of links to
+/// external urls.
+///
+/// Groups:
+/// 1: text that isn't protected, but mustn't start a protected region either,
+///    like the `[[$color]]` of a highlight or the `](file://...)` of a local
+///    file, which is copied like an asset
+fn protected_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| {
+        Regex::new(
+            r"(?s)(\[\[\$\w+\]\]|\]\(file://(?:[^()\s]|\([^()\s]*\))*\))|```.*?(?:```|\z)|`[^`\n]+`|\$\$.*?\$\$|\$[^\s$](?:[^$\n]*[^\s$])?\$|\]\([a-zA-Z][a-zA-Z0-9+.\-]*://(?:[^()\s]|\([^()\s]*\))*\)",
+        )
+        .unwrap()
+    })
+}
+
+/// Groups:
+/// 1: index of the masked region
+fn mask_placeholder_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| Regex::new(r"\x{E000}(\d+)\x{E001}").unwrap())
+}
+
+fn drawer_re() -> &'static Regex {
+    static RE: OnceCell<Regex> = OnceCell::new();
+    RE.get_or_init(|| Regex::new(r"^\s*:[A-Za-z][\w-]*:\s*$").unwrap())
+}
+
+/// Extensions of linked files that are copied like assets.
+const ASSET_EXTENSIONS: &[&str] = &[
+    "pdf", "mp3", "mp4", "m4a", "wav", "ogg", "webm", "mov", "zip", "epub", "docx", "xlsx", "pptx",
+];
+
+/// Whether the link `url` points at an asset instead of a page.
+fn is_asset_link(url: &str) -> bool {
+    let path = Path::new(url);
+    path.components().any(|c| c.as_os_str() == "assets")
+        || path
+            .extension()
+            .map(|e| ASSET_EXTENSIONS.contains(&e.to_string_lossy().to_lowercase().as_str()))
+            .unwrap_or(false)
+}
+
+/// Whether following the block references of the block `logseq_id` leads back
+/// to a block in `visited`.
+fn refers_back(logseq_id: &str, data: &dyn Data, visited: &mut Vec<String>) -> bool {
+    if visited.iter().any(|v| v == logseq_id) {
+        return true;
+    }
+    let text = match data.query_block_text(logseq_id) {
+        Some(text) => text.to_owned(),
+        None => return false,
+    };
+    visited.push(logseq_id.to_owned());
+    let cycle = block_ref_re()
+        .captures_iter(&text)
+        .any(|c| refers_back(&c[1], data, visited));
+    visited.pop();
+    cycle
+}
+
+/// Returns the text of the block `logseq_id` with its standalone block
+/// references inlined as well, references back to a block in `visited` stay
+/// links.
+fn inline_ref_text(
+    logseq_id: &str,
+    data: &mut dyn Data,
+    visited: &mut Vec<String>,
+) -> Option<String> {
+    let text = data.query_block_text(logseq_id)?.to_owned();
+    visited.push(logseq_id.to_owned());
+    let mut result = text.clone();
+    for m in block_ref_re()
+        .captures_iter(&text)
+        .collect_vec()
+        .into_iter()
+        .rev()
+    {
+        let id = &m[1];
+        let inlined = match visited.iter().any(|v| v == id) {
+            true => {
+                warn_cycle(id, data);
+                None
+            }
+            false => inline_ref_text(id, data, visited),
+        };
+        let replacement = match inlined {
+            Some(inlined) => inlined,
+            None => match data.query_id(id) {
+                Some(r) => format!("[[{}]]", r.get_link(data.curr_title())),
+                None => continue,
+            },
+        };
+        result.replace_range(m.get(0).unwrap().range(), &replacement);
+    }
+    visited.pop();
+    Some(result.lines().map(str::trim).join(" "))
+}
+
+/// Transforms the logseq text `text` of a referenced block like the block
+/// itself, so that it can be inlined.
+fn transform_inlined(text: String, data: &mut dyn Data) -> String {
+    let mut block = Block {
+        text,
+        ..Default::default()
+    };
+    block.transform(None, None, data);
+    // Tasks become list items, the bullet doesn't belong in the line.
+    let text = block.text.strip_prefix("- ").unwrap_or(&block.text);
+    text.lines().map(str::trim).join(" ")
+}
+
+fn warn_cycle(logseq_id: &str, data: &mut dyn Data) {
+    let message = format!(
+        "Circular block reference (({logseq_id})) in '{}'",
+        data.curr_title()
+    );
+    data.warn(&message);
+}
+
+/// Copies the asset `path` using [`Data::copy_asset`], warning if that fails.
+fn copy_asset(path: &str, data: &mut dyn Data) -> Option<String> {
+    match data.copy_asset(path) {
+        Ok(new_path) => Some(new_path),
+        Err(e) => {
+            let message = format!(
+                "Could not copy asset '{path}' of '{}': {e:#}",
+                data.curr_title()
+            );
+            data.warn(&message);
+            None
+        }
+    }
+}
+
+impl Block {
+    fn transform(
+        &mut self,
+        parent: Option<&Block>,
+        prev_sibling: Option<&Block>,
+        data: &mut dyn Data,
+    ) {
+        #[allow(clippy::mem_replace_with_default)]
+        let mut children = std::mem::replace(&mut self.children, Vec::new());
+        for i in 0..children.len() {
+            let (prev, rest) = children.as_mut_slice().split_at_mut(i);
+            let (curr, _rest) = rest.split_at_mut(1);
+            let curr = curr.first_mut().unwrap();
+            curr.transform(Some(self), prev.last(), data);
+        }
+        // Blocks that only had properties are left without any text.
+        children.retain(|c| !c.is_empty(data));
+        if data.config().flatten_empty_groups {
+            children = flatten_empty_groups(children);
+        }
+        self.children = children;
+
+        let parent_none_or_normal = parent.map(|p| !p.is_list_item).unwrap_or(true);
+        let prev_none_or_normal = prev_sibling.map(|p| !p.is_list_item).unwrap_or(true);
+
+        // Reports the passes that changed the text, the text is only kept while
+        // tracing. Masking the text isn't reported, so the passes see placeholders.
+        let mut last = data.tracing().then(|| self.text.clone());
+        macro_rules! traced {
+            () => {
+                if let Some(before) = &mut last {
+                    *before = self.text.clone();
                 }
+            };
+            ($pass:literal) => {
+                if let Some(before) = &mut last {
+                    if *before != self.text {
+                        data.trace($pass, before, &self.text);
+                        *before = self.text.clone();
+                    }
+                }
+            };
+        }
 
-                if curr.trim_start().starts_with('-') {
-                    first_child = Some(curr);
-                    Some(l)
-                } else {
-                    None
+        let masked = mask(&mut self.text, protected_re());
+        traced!();
+        self.text = latex_math_re()
+            .replace_all(&self.text, |c: &regex::Captures| {
+                match (c.get(1), c.get(2)) {
+                    (Some(inline), _) => format!("${}$", inline.as_str().trim()),
+                    (_, Some(display)) => format!("$${}$$", display.as_str()),
+                    _ => unreachable!(),
                 }
             })
-            .map(|l| unsafe { union_str(text, l) })
-            .unwrap_or(text);
+            .to_string();
+        traced!("latex");
+        self.text = unmask(&self.text, &masked);
+        traced!();
 
-        let mut is_list_item = body.starts_with("- ");
+        if parent_none_or_normal && only_math_re().is_match(&self.text) {
+            self.set_list_item(false);
+        }
 
-        let mut body = body
-            .strip_prefix("- ")
-            .unwrap_or(&body)
-            .lines()
-            .filter(|l| match parse_prop(l) {
-                Some((Prop::Id, val)) => {
-                    id = Some(val);
-                    false
+        if rule_re().is_match(&self.text) || is_table(&self.text) {
+            self.set_list_item(false);
+        }
+
+        // Top level headings move up a level.
+        let level = (self.text.lines().next())
+            .and_then(|l| header_re().captures(l)?.get(1))
+            .filter(|l| l.end() - l.start() > 1)
+            .map(|l| l.start());
+        if let (None, Some(start)) = (parent, level) {
+            self.text.remove(start);
+        }
+        traced!("headings");
+
+        if parent_none_or_normal && prev_none_or_normal && only_image_re().is_match(&self.text) {
+            self.set_list_item(false);
+        }
+        traced!("list items");
+
+        self.convert_task(data);
+        traced!("tasks");
+        self.convert_task_dates(data);
+        traced!("task dates");
+
+        let masked = mask(&mut self.text, protected_re());
+        traced!();
+
+        let drop_queries = data.config().drop_queries;
+        self.text = query_block_re()
+            .replace_all(&self.text, |c: &regex::Captures| match drop_queries {
+                true => String::new(),
+                false => format!("%% logseq query:\n{}\n%%", c[1].trim_end()),
+            })
+            .to_string();
+        self.text = query_re()
+            .replace_all(&self.text, |c: &regex::Captures| match drop_queries {
+                true => String::new(),
+                false => format!("%% logseq query: {} %%", &c[1]),
+            })
+            .to_string();
+        traced!("queries");
+        let drop_renderers = data.config().drop_renderers;
+        self.text = renderer_re()
+            .replace_all(&self.text, |c: &regex::Captures| match drop_renderers {
+                true => String::new(),
+                false => format!("%% logseq renderer: {} %%", &c[1]),
+            })
+            .to_string();
+        traced!("renderers");
+
+        let config = data.config();
+        self.text = macro_re()
+            .replace_all(&self.text, |c: &regex::Captures| {
+                let name = c[1].to_lowercase();
+                let args = c.get(2).map_or("", |a| a.as_str().trim());
+                match MACROS.iter().find(|(n, _)| *n == name) {
+                    _ if OWN_PASS_MACROS.contains(&name.as_str()) => c[0].to_owned(),
+                    Some((_, handler)) => {
+                        let args = args.strip_prefix("[[").unwrap_or(args);
+                        handler(args.strip_suffix("]]").unwrap_or(args), config)
+                    }
+                    None => format!("%% unsupported macro: {} %%", &c[0][2..c[0].len() - 2]),
                 }
-                None => true,
-                _ => false,
             })
-            .join("\n");
-        if is_list_item {
-            body = format! {"- {}", trim_start_up_to(2, &body)}
+            .to_string();
+        traced!("macros");
+
+        self.text = org_block_re()
+            .replace_all(&self.text, |c: &regex::Captures| {
+                let kind = c[2].to_uppercase();
+                let callout = match kind.as_str() {
+                    _ if !c[4].eq_ignore_ascii_case(&kind) => return c[0].to_owned(),
+                    "QUOTE" => None,
+                    "NOTE" | "WARNING" | "TIP" | "IMPORTANT" | "CAUTION" => {
+                        Some(kind.to_lowercase())
+                    }
+                    _ => return c[0].to_owned(),
+                };
+                let indent = &c[1];
+                let header = callout.map(|c| format!("{indent}> [!{c}]\n"));
+                let content = c[3]
+                    .lines()
+                    .map(|l| match trim_start_up_to(indent.len(), l) {
+                        "" => format!("{indent}>"),
+                        l => format!("{indent}> {l}"),
+                    })
+                    .join("\n");
+                format!("{}{content}", header.unwrap_or_default())
+            })
+            .to_string();
+        traced!("org blocks");
+
+        let mut text = self.text.clone();
+        for m in cloze_re()
+            .captures_iter(&self.text)
+            .collect_vec()
+            .into_iter()
+            .rev()
+        {
+            let (whole, answer) = match (m.get(0), m.get(1)) {
+                (Some(w), Some(a)) => (w, a.as_str().trim()),
+                _ => continue,
+            };
+            let cloze = match data.config().cloze_style {
+                ClozeStyle::Highlight => format!("=={answer}=="),
+                ClozeStyle::Braces => format!("{{{{{answer}}}}}"),
+            };
+            text.replace_range(whole.range(), &cloze);
         }
+        self.text = text;
+        traced!("clozes");
 
-        let mut self_border = false;
+        let html_highlights = data.config().html_highlights;
+        self.text = colored_highlight_re()
+            .replace_all(&self.text, |c: &regex::Captures| {
+                let text = c.get(2).or(c.get(3)).unwrap().as_str();
+                match html_highlights {
+                    true => format!("<mark style=\"background: {}\">{text}</mark>", &c[1]),
+                    false => format!("=={text}=="),
+                }
+            })
+            .to_string();
+        self.text = highlight_re().replace_all(&self.text, "==$1==").to_string();
+        traced!("highlights");
 
-        if body.starts_with("- **") || body.starts_with("- #") {
-            body = list_item_to_normal(&body);
-            is_list_item = false;
+        let tags_as_links = data.config().tags_as_links;
+        self.text = hashtag_re()
+            .replace_all(&self.text, |c: &regex::Captures| {
+                if tags_as_links {
+                    format!("[[{}]]", &c[1])
+                } else {
+                    format!("#{}", c[1].trim().replace(' ', "-"))
+                }
+            })
+            .to_string();
+        traced!("hashtags");
+
+        self.text = page_link_re()
+            .replace_all(&self.text, |c: &regex::Captures| {
+                if let Some(journal) = data.journal_link(&c[1]) {
+                    return format!("[[{journal}]]");
+                }
+                // Obsidian doesn't resolve links to aliases.
+                let title = data.query_alias(&c[1]);
+                let target = data
+                    .page_link(title.unwrap_or(&c[1]))
+                    .or(title.map(ToOwned::to_owned));
+                match target {
+                    Some(target) => format!("[[{target}|{}]]", &c[1]),
+                    None => c[0].to_owned(),
+                }
+            })
+            .to_string();
+        traced!("journal links");
+
+        let mut text = self.text.clone();
+        for m in draw_re()
+            .captures_iter(&self.text)
+            .collect_vec()
+            .into_iter()
+            .rev()
+        {
+            let (whole, path) = match (m.get(0), m.get(1)) {
+                (Some(w), Some(p)) => (w, p.as_str()),
+                _ => continue,
+            };
+            // Pages are one level below the graph.
+            let path = match path.starts_with("../") {
+                true => path.to_owned(),
+                false => format!("../{path}"),
+            };
+            if let Some(new_path) = copy_asset(&path, data) {
+                text.replace_range(whole.range(), &format!("![[{new_path}]]"));
+            }
         }
-        if self_border_re().is_match(&body) {
-            self_border = true;
-            body = self_border_re().replace_all(&body, "").to_string();
+        self.text = text;
+        traced!("draws");
+
+        let mut text = self.text.clone();
+        for m in image_re()
+            .captures_iter(&self.text)
+            .collect_vec()
+            .into_iter()
+            .rev()
+        {
+            let (whole, name, path) = match (m.get(0), m.get(1), m.get(2)) {
+                (Some(w), Some(n), Some(p)) => (w, n, p),
+                _ => continue,
+            };
+            if path.as_str().contains("://") && !path.as_str().starts_with("file://") {
+                continue;
+            }
+
+            let new_path = match copy_asset(path.as_str(), data) {
+                Some(new_path) => new_path,
+                None => continue,
+            };
+
+            let annotation = m.get(3).map_or("", |a| a.as_str());
+            let width = image_width_re()
+                .captures(annotation)
+                .map(|c| c[1].to_owned());
+            // The caption is either annotated or, with `italic_captions`, the
+            // italic line after the image.
+            let caption_line = caption_line_re()
+                .captures(&self.text[whole.end()..])
+                .filter(|_| data.config().italic_captions)
+                .map(|c| {
+                    let caption = c.get(1).or_else(|| c.get(2)).unwrap().as_str();
+                    let line = c.get(0).unwrap();
+                    let end = whole.end() + line.as_str().trim_end_matches('\n').len();
+                    (whole.end()..end, caption.trim().to_owned())
+                });
+            let caption = image_caption_re()
+                .captures(annotation)
+                .map(|c| c[1].to_owned())
+                .or_else(|| caption_line.as_ref().map(|(_, c)| c.clone()))
+                .filter(|c| !c.is_empty());
+            if let Some((line, _)) = caption_line {
+                text.replace_range(line, "");
+            }
+            let alt = caption.as_deref().filter(|_| !data.config().captions_below);
+            let name = alt.unwrap_or(name.as_str());
+            let width = match (width, alt) {
+                (Some(width), Some(alt)) => Some(format!("{alt}|{width}")),
+                (width, _) => width,
+            };
+            let mut image = match width {
+                // The Excalidraw plugin only renders embeds.
+                _ if is_draw(&new_path) => format!("![[{new_path}]]"),
+                Some(width) => format!("![[{new_path}|{width}]]"),
+                None if data.config().image_embeds && name.is_empty() => format!("![[{new_path}]]"),
+                None if data.config().image_embeds => format!("![[{new_path}|{name}]]"),
+                None => format!("![{name}]({new_path})"),
+            };
+            if let Some(caption) = caption.filter(|_| data.config().captions_below) {
+                image += &format!("\n*{caption}*");
+            }
+            text.replace_range(whole.range(), &image);
+        }
+
+        self.text = text.clone();
+        traced!("images");
+        for m in file_link_re()
+            .captures_iter(&self.text)
+            .collect_vec()
+            .into_iter()
+            .rev()
+        {
+            let (whole, title, url) = match (m.get(0), m.get(1), m.get(2)) {
+                (Some(a), Some(b), Some(c)) => (a, b, c),
+                _ => continue,
+            };
+            let url = url.as_str();
+            let title = title.as_str();
+            let url = if is_asset_link(url) {
+                match copy_asset(url, data) {
+                    Some(new_path) => new_path,
+                    None => continue,
+                }
+            } else {
+                url.to_owned()
+            };
+            text.replace_range(whole.range(), &format!("[[{url}|{title}]]"));
         }
 
-        let header = body.lines().next().and_then(|l| {
-            let c = header_re().captures(l)?;
-            Some(c.get(1)?.as_str().to_owned())
-        });
+        // The block itself, references back to it are cycles.
+        let own_id = self.id.iter().map(|id| id.logseq_id.clone()).collect_vec();
+        self.text = text.clone();
+        traced!("file links");
+        for m in embed_id_re()
+            .captures_iter(&self.text)
+            .collect_vec()
+            .into_iter()
+            .rev()
+        {
+            let (whole, id) = match (m.get(0), m.get(1)) {
+                (Some(n), Some(p)) => (n, p),
+                _ => continue,
+            };
+            if let Some(r) = data.query_id(id.as_str()) {
+                // Obsidian embeds the section of `#Heading` anchors, the block of
+                // `#^id` ones and the whole page without an anchor.
+                let link = r.get_link(data.curr_title());
+                // Obsidian can't size block embeds, their annotations are dropped.
+                text.replace_range(whole.range(), &format!("![[{link}]]"));
+                data.resolved_ref(id.as_str());
+                if refers_back(id.as_str(), data, &mut own_id.clone()) {
+                    warn_cycle(id.as_str(), data);
+                }
+            } else {
+                data.unresolved_ref(id.as_str());
+            }
+        }
+
+        self.text = page_embed_re()
+            .replace_all(&text, |c: &regex::Captures| {
+                let target = format!("{}{}", &c[1], c.get(2).map_or("", |h| h.as_str()));
+                embed(&target, c.get(3).map(|a| a.as_str()))
+            })
+            .to_string();
+        traced!("embeds");
+        // Like images, blocks that only embed a page or block are shown on their own.
+        let only_embed = only_embed_re().captures(&self.text);
+        if parent_none_or_normal
+            && only_embed.is_some_and(|c| !is_asset_link(&c[1]) && !is_draw(&c[1]))
+        {
+            self.set_list_item(false);
+            traced!("list items");
+        }
+        text = self.text.clone();
+        for m in link_id_re()
+            .captures_iter(&self.text)
+            .collect_vec()
+            .into_iter()
+            .rev()
+        {
+            let (whole, title, id) = match (m.get(0), m.get(1), m.get(2)) {
+                (Some(w), Some(a), Some(b)) => (w, a, b),
+                _ => continue,
+            };
+            if let Some(r) = data.query_id(id.as_str()) {
+                let link = r.get_link(data.curr_title());
+                let title = title.as_str();
+                text.replace_range(whole.range(), &format!("[[{link}|{title}]]"));
+                data.resolved_ref(id.as_str());
+            } else {
+                data.unresolved_ref(id.as_str());
+            }
+        }
+
+        self.text = text.clone();
+        traced!("link refs");
+        for m in standalone_id_re()
+            .captures_iter(&self.text)
+            .collect_vec()
+            .into_iter()
+            .rev()
+        {
+            let (url, id) = match (m.get(1), m.get(2)) {
+                (Some(n), Some(p)) => (n, p),
+                _ => continue,
+            };
+            let cycle = refers_back(id.as_str(), data, &mut own_id.clone());
+            let inline = match data.config().inline_refs && !cycle {
+                true => inline_ref_text(id.as_str(), data, &mut own_id.clone())
+                    .map(|t| transform_inlined(t, data)),
+                false => None,
+            };
+            if cycle {
+                warn_cycle(id.as_str(), data);
+            }
+            if let Some(block_text) = inline {
+                text.replace_range(url.range(), &block_text);
+                data.resolved_ref(id.as_str());
+            } else if let Some(r) = data.query_id(id.as_str()) {
+                let link = r.get_link(data.curr_title());
+                text.replace_range(url.range(), &format!("[[{link}]]"));
+                data.resolved_ref(id.as_str());
+            } else {
+                data.unresolved_ref(id.as_str());
+            }
+        }
+        self.text = text;
+        traced!("block refs");
+
+        for (re, replacement) in &data.config().replacements {
+            let mut text = self.text.clone();
+            for c in re.captures_iter(&self.text).collect_vec().into_iter().rev() {
+                let mut new = String::new();
+                c.expand(replacement, &mut new);
+                text.replace_range(c.get(0).unwrap().range(), &new);
+            }
+            self.text = text;
+        }
+        traced!("replacements");
+        self.text = unmask(&self.text, &masked);
+        traced!();
+
+        if data.config().hard_breaks && !self.is_list_item {
+            self.text = hard_breaks(&self.text);
+            traced!("hard breaks");
+        }
+
+        self.convert_card(data);
+        traced!("cards");
+    }
+
+    /// Converts a block tagged with `#card` and its first child to a multi-line
+    /// question and answer of the Spaced Repetition plugin.
+    fn convert_card(&mut self, data: &dyn Data) {
+        if self.children.is_empty() || !card_tag_re().is_match(&self.text) {
+            return;
+        }
+        self.set_list_item(false);
+        let question = card_tag_re().replace_all(&self.text, "").trim().to_owned();
+
+        let answer = self.children.remove(0);
+        let answer_text = answer.to_string(false, data);
+        let answer_text = if answer.is_list_item {
+            list_item_to_normal(&answer_text)
+        } else {
+            answer_text
+        };
+        self.text = format!("{question}\n?\n{}", answer_text.trim_end());
+    }
+
+    pub fn parse(text: &str, data: &mut dyn Data) -> Result<Self> {
+        Self::parse_with_anchors(text, data, &mut HashSet::new())
+    }
+
+    /// Parses the block `text`, `anchors` are the block anchors of the page
+    /// that are already taken.
+    fn parse_with_anchors(
+        text: &str,
+        data: &mut dyn Data,
+        anchors: &mut HashSet<String>,
+    ) -> Result<Self> {
+        let mut first_child = None;
+        let mut id = None;
+        let body = text
+            .lines()
+            .chain([""])
+            .tuple_windows::<(&str, &str)>()
+            .find_map(|(l, curr)| {
+                if let Some((Prop::Id, val)) = parse_prop(curr) {
+                    id = Some(val);
+                    return None;
+                }
+
+                if curr.trim_start().starts_with('-') {
+                    first_child = Some(curr);
+                    Some(l)
+                } else {
+                    None
+                }
+            })
+            .map(|l| unsafe { union_str(text, l) })
+            .unwrap_or(text);
+
+        // Empty bullets are written as a bare `-`.
+        let mut is_list_item = body.starts_with("- ") || body.lines().next() == Some("-");
+        let mut collapsed = false;
+        let mut ordered = false;
+        let mut callout = None;
+
+        let drop_props = data.config().block_props == BlockProps::Drop;
+        let mut in_code = false;
+        let (lines, drawers) = strip_drawers(
+            body.strip_prefix("- ")
+                .or_else(|| body.strip_prefix('-').filter(|_| is_list_item))
+                .unwrap_or(body)
+                .lines(),
+        );
+        let done = task_re().captures(body).is_some_and(|c| &c[1] == "DONE");
+        let completed = drawers
+            .iter()
+            .filter(|l| done && (l.trim_start().starts_with("CLOCK:") || l.contains(":COMPLETED:")))
+            .flat_map(|l| logbook_date_re().captures_iter(l))
+            .map(|c| c[1].to_owned())
+            .max();
+        let mut body = lines
+            .into_iter()
+            .filter_map(|l| match parse_prop(l) {
+                Some((Prop::Id, val)) => {
+                    id = Some(val);
+                    None
+                }
+                Some((Prop::Collapsed, val)) => {
+                    collapsed = val.trim() == "true";
+                    None
+                }
+                Some((Prop::OrderListType, val)) => {
+                    ordered = val.trim() == "number";
+                    None
+                }
+                Some((Prop::BackgroundColor, val)) => {
+                    callout = Some(color_callout(val).to_owned());
+                    None
+                }
+                None => {
+                    if l.trim_start().starts_with("```") {
+                        in_code = !in_code;
+                    }
+                    if in_code {
+                        return Some(l);
+                    }
+                    if let Some(c) = trailing_id_re().captures(l) {
+                        id = Some(c.get(1).unwrap().as_str());
+                        return Some(&l[..c.get(0).unwrap().start()]);
+                    }
+                    (!drop_props || parse_any_prop(l).is_none()).then_some(l)
+                }
+                _ => None,
+            })
+            .join("\n");
+        if is_list_item {
+            body = format! {"- {}", trim_start_up_to(2, &body)}
+        }
+        if is_list_item && body.trim() == "-" {
+            body = "- ".to_owned();
+        }
+
+        let mut self_border = false;
+
+        if (body.starts_with("- **") || body.starts_with("- #"))
+            && !data.config().keep_bold_headers_as_list
+        {
+            body = list_item_to_normal(&body);
+            is_list_item = false;
+        }
+        // Footnote definitions only work outside of lists.
+        if is_list_item && footnote_def_re().is_match(&body) {
+            body = list_item_to_normal(&body);
+            is_list_item = false;
+        }
+        if self_border_re().is_match(&body) {
+            self_border = true;
+            body = self_border_re().replace_all(&body, "").to_string();
+        }
+
+        let header = body.lines().next().and_then(|l| {
+            let c = header_re().captures(l)?;
+            Some(c.get(2)?.as_str().to_owned())
+        });
+
+        let id = id.map(|id| {
+            let obsdn_id = if let Some(header) = &header {
+                // The anchor has to match the heading after `transform`.
+                let h = highlight_re().replace_all(header, "==$1==");
+                let h = header_san_re().replace_all(&h, " ").trim().to_string();
+                format!("#{h}")
+            } else {
+                let slug = match data.config().anchor_style {
+                    AnchorStyle::Slug => slug(&body),
+                    AnchorStyle::Hash => String::new(),
+                };
+                let base = if slug.is_empty() {
+                    format!("{:x}", fnv1a(body.as_bytes()))
+                } else {
+                    slug
+                };
+                // Blocks with the same text get the same anchor, that of a
+                // later block is suffixed.
+                let mut anchor = base.clone();
+                let mut suffix = 0;
+                while !anchors.insert(anchor.clone()) {
+                    suffix += 1;
+                    anchor = format!("{base}-{suffix}");
+                }
+                format!("^{anchor}")
+            };
+
+            let id = Id {
+                obsdn_id,
+                logseq_id: id.to_string(),
+                text: body.strip_prefix("- ").unwrap_or(&body).to_owned(),
+                page: data.curr_title().to_owned(),
+            };
+            data.register_id(&id);
+            id
+        });
+
+        let children = if let Some(first_child) = first_child {
+            let num_spaces = first_child
+                .chars()
+                .take_while(|c| *c == ' ' || *c == '\t')
+                .count();
+            let c = first_child.trim_start().chars().next().unwrap();
+
+            let lines = unsafe { union_str(first_child, text) }
+                .lines()
+                .map(|l| trim_start_up_to(num_spaces, l));
+
+            blocks(lines, c)
+                .into_iter()
+                .filter(|s| !s.trim().is_empty())
+                .map(|l| Block::parse_with_anchors(&l, data, anchors))
+                .try_collect()?
+        } else {
+            vec![]
+        };
+
+        Ok(Self {
+            text: body,
+            header,
+            children,
+            #[allow(clippy::useless_conversion)]
+            id: id.map(Into::into),
+            self_border,
+            is_list_item,
+            collapsed,
+            ordered,
+            completed,
+            callout,
+        })
+    }
+
+    /// Converts a leading logseq task keyword to a checkbox.
+    fn convert_task(&mut self, data: &dyn Data) {
+        // Existing checkboxes are already valid in obsidian.
+        if checkbox_re().is_match(&self.text) || !task_re().is_match(&self.text) {
+            return;
+        }
+        // Obsidian only has checkboxes in list items.
+        self.set_list_item(true);
+
+        let keyword = task_re().captures(&self.text).unwrap().get(1).unwrap();
+        let done = matches!(keyword.as_str(), "DONE" | "CANCELED" | "CANCELLED");
+        let checkbox = match keyword.as_str() {
+            _ if done => "[x]",
+            "DOING" | "NOW" if data.config().rich_task_states => "[/]",
+            "WAITING" if data.config().rich_task_states => "[?]",
+            _ => "[ ]",
+        };
+        self.text.replace_range(keyword.range(), checkbox);
+
+        // Priorities become the emoji of the Tasks plugin at the end of the line.
+        let priority = task_priority_re().captures(&self.text).map(|c| {
+            let emoji = match &c[2] {
+                "A" => "🔺",
+                "B" => "🔼",
+                _ => "🔽",
+            };
+            (c.get(1).unwrap().range(), emoji)
+        });
+        if let Some((token, _)) = &priority {
+            self.text.replace_range(token.clone(), "");
+        }
+
+        if done && data.config().strike_done {
+            let start = "- [x] ".len();
+            let end = self.text.find('\n').unwrap_or(self.text.len());
+            let task = self.text[start..end].trim_end();
+            if !task.is_empty() {
+                let struck = format!("~~{task}~~");
+                self.text.replace_range(start..end, &struck);
+            }
+        }
+
+        if let Some((_, emoji)) = priority {
+            let end = self.text.find('\n').unwrap_or(self.text.len());
+            let task_end = self.text[..end].trim_end().len();
+            self.text.replace_range(task_end..end, &format!(" {emoji}"));
+        }
+    }
+
+    /// Removes the `SCHEDULED`/`DEADLINE` lines and appends their dates to the
+    /// first line as Obsidian Tasks dates.
+    fn convert_task_dates(&mut self, data: &dyn Data) {
+        let (date_lines, lines): (Vec<_>, Vec<_>) = self
+            .text
+            .lines()
+            .partition(|l| task_dates_line_re().is_match(l));
+        if date_lines.is_empty() && self.completed.is_none() {
+            return;
+        }
+
+        let mut text = lines.join("\n");
+        if data.config().task_dates {
+            let dates = date_lines
+                .iter()
+                .flat_map(|l| task_date_re().captures_iter(l))
+                .map(|c| {
+                    let emoji = if &c[1] == "SCHEDULED" { "⏳" } else { "📅" };
+                    format!(" {emoji} {}", &c[2])
+                })
+                .chain(self.completed.iter().map(|d| format!(" ✅ {d}")))
+                .join("");
+            let first_line_end = text.find('\n').unwrap_or(text.len());
+            text.insert_str(first_line_end, &dates);
+        }
+        self.text = text;
+    }
+
+    /// Whether the block is rendered as a callout.
+    fn is_callout(&self) -> bool {
+        self.self_border || self.callout.is_some()
+    }
+
+    /// Whether the block is a horizontal rule.
+    fn is_rule(&self) -> bool {
+        !self.is_list_item && rule_re().is_match(&self.text)
+    }
+
+    /// Whether the block has neither text nor children.
+    /// Whether the block has no text and no children, blocks whose anchor is
+    /// written aren't empty, references link to them.
+    fn is_empty(&self, data: &dyn Data) -> bool {
+        let text = self.text.trim();
+        self.children.is_empty()
+            && (text.is_empty() || text == "-")
+            && !(self.id.as_ref()).is_some_and(|id| data.is_referenced(&id.logseq_id))
+    }
+
+    /// Whether the block has no text and only exists to group its children.
+    fn is_empty_group(&self) -> bool {
+        let text = self.text.trim();
+        !self.children.is_empty()
+            && (text.is_empty() || text == "-")
+            && self.id.is_none()
+            && !self.is_callout()
+    }
+
+    pub fn set_list_item(&mut self, is_list_item: bool) {
+        if is_list_item == self.is_list_item {
+            return;
+        }
+        self.is_list_item = is_list_item;
+        if is_list_item {
+            self.text = normal_to_list_item(&self.text);
+        } else {
+            self.text = list_item_to_normal(&self.text);
+        }
+    }
+
+    pub fn to_string(&self, is_last: bool, data: &dyn Data) -> String {
+        let n = self.children.len().saturating_sub(1);
+
+        let children = self
+            .children
+            .iter()
+            .zip(list_numbers(&self.children))
+            .enumerate()
+            .map(|(i, (c, number))| {
+                let indent = if c.is_list_item && self.is_list_item {
+                    repeat_space(4)
+                } else if self.is_list_item {
+                    repeat_space(2)
+                } else {
+                    repeat_space(0)
+                };
+                let child = number_list_item(c.to_string(i == n, data), number);
+                // Without blank lines a rule would turn the line before into a
+                // heading, and a table would be part of it.
+                let child = match c.is_rule() || (!c.is_list_item && is_table(&c.text)) {
+                    true => format!("\n{child}\n"),
+                    false => child,
+                };
+                // Empty lines stay empty, instead of ending with the indent.
+                child
+                    .split("\n")
+                    .map(|l| match l.is_empty() {
+                        true => String::new(),
+                        false => format!("{indent}{l}"),
+                    })
+                    .join("\n")
+            })
+            .collect_vec()
+            .join("\n");
+        let mut text = self.text.clone();
+        if self.is_list_item && text == "- " && !self.children.is_empty() {
+            // A bullet that only groups its children.
+            text.truncate(1);
+        }
+        if self.is_list_item {
+            text = indent_continuation_lines(&text);
+        }
+        if let Some(id) = self.id.as_ref().filter(|_| data.config().keep_logseq_ids) {
+            // Before the anchor, which has to end the block. Headings and
+            // callouts get it on a line of its own.
+            let sep = if self.header.is_some() || self.is_callout() {
+                "\n"
+            } else {
+                " "
+            };
+            text += &format!("{sep}%% logseq-id: {} %%", id.logseq_id);
+        }
+        let id = self
+            .id
+            .as_ref()
+            .and_then(|id| {
+                if self.header.is_some() || !data.is_referenced(&id.logseq_id) {
+                    return None;
+                }
+                if self.is_callout() {
+                    Some(format!("\n{}\n", id.obsdn_id))
+                } else {
+                    Some(format!(" {}", id.obsdn_id))
+                }
+            })
+            .unwrap_or_default();
+
+        #[allow(clippy::obfuscated_if_else)]
+        let before = (!self.children.is_empty())
+            .then_some("\n")
+            .unwrap_or_default();
+        #[allow(clippy::obfuscated_if_else)]
+        let after = (is_last && self.children.is_empty())
+            .then_some("\n")
+            .unwrap_or_default();
+
+        if self.is_callout() {
+            let children = children.trim_end();
+
+            // Colors only exist as native callouts.
+            match (&self.callout, data.config().callout_style) {
+                (None, CalloutStyle::Admonition) => {
+                    let content = format!("{text}{before}{children}");
+                    let fence = "`".repeat(fence_len(&content));
+                    format!("{fence}ad-def\n{content}\n{fence}\n{id}")
+                }
+                (callout, _) => {
+                    let content = format!("{text}{before}{children}")
+                        .lines()
+                        .map(|l| {
+                            if l.is_empty() {
+                                ">".to_owned()
+                            } else {
+                                format!("> {l}")
+                            }
+                        })
+                        .join("\n");
+                    let kind = callout.as_deref().unwrap_or("note");
+                    format!("> [!{kind}]\n{content}\n{id}")
+                }
+            }
+        } else {
+            // Blocks without text are only their bullet and the anchor.
+            let text = match id.starts_with(' ') {
+                true => text.trim_end_matches(' '),
+                false => &text,
+            };
+            format!("{text}{id}{before}{children}{after}")
+        }
+    }
+}
+
+/// Returns the number of backticks a fence around `content` needs, one more
+/// than the longest fence inside of it.
+fn fence_len(content: &str) -> usize {
+    content
+        .lines()
+        .map(|l| l.trim_start().chars().take_while(|c| *c == '`').count())
+        .filter(|n| *n >= 3)
+        .map(|n| n + 1)
+        .max()
+        .unwrap_or(3)
+}
+
+/// Numbers the consecutive numbered list items of `blocks`, starting at 1.
+fn list_numbers(blocks: &[Block]) -> Vec<Option<usize>> {
+    let mut number = 0;
+    blocks
+        .iter()
+        .map(|b| {
+            if b.ordered && b.is_list_item {
+                number += 1;
+                Some(number)
+            } else {
+                number = 0;
+                None
+            }
+        })
+        .collect()
+}
+
+/// Replaces the bullet of the rendered list item `item` with `number`.
+fn number_list_item(item: String, number: Option<usize>) -> String {
+    match (number, item.strip_prefix("- ")) {
+        (Some(number), Some(rest)) => format!("{number}. {rest}"),
+        _ => item,
+    }
+}
+
+#[derive(strum::EnumIter, strum::AsRefStr, Debug, Clone, Copy)]
+pub enum Prop {
+    #[strum(serialize = "title::")]
+    Title,
+    #[strum(serialize = "alias::")]
+    Alias,
+    #[strum(serialize = "id::")]
+    Id,
+    #[strum(serialize = "collapsed::")]
+    Collapsed,
+    #[strum(serialize = "logseq.order-list-type::")]
+    OrderListType,
+    #[strum(serialize = "background-color::")]
+    BackgroundColor,
+}
+
+/// Returns the native callout type of the logseq block color `color`.
+fn color_callout(color: &str) -> &'static str {
+    match color.trim().trim_matches('"') {
+        "green" => "tip",
+        "red" => "danger",
+        "yellow" => "warning",
+        "blue" => "info",
+        "purple" => "example",
+        "pink" => "bug",
+        "gray" => "quote",
+        _ => "note",
+    }
+}
+fn parse_prop(line: &str) -> Option<(Prop, &str)> {
+    let line = line.trim();
+
+    for e in Prop::iter() {
+        if let Some(suffix) = line.strip_prefix(e.as_ref()) {
+            return Some((e, suffix.trim_start()));
+        }
+    }
+    None
+}
+
+/// Rewrites an org-mode page into the markdown form of logseq pages, so that its
+/// block structure and properties are parsed.
+///
+/// Only the `*` outline, `#+key: value` page properties and `:PROPERTIES:`
+/// drawers are converted, everything else is kept as is.
+fn org_to_markdown(text: &str) -> String {
+    let mut indent = String::new();
+    let mut in_drawer = false;
+    let mut lines = vec![];
+    for line in text.lines() {
+        let trimmed = line.trim();
+        let stars = trimmed.chars().take_while(|c| *c == '*').count();
+        if stars > 0 && trimmed[stars..].starts_with(' ') {
+            let block_indent = repeat_space(2 * (stars - 1));
+            lines.push(format!("{block_indent}- {}", trimmed[stars..].trim_start()));
+            indent = format!("{block_indent}  ");
+        } else if trimmed.eq_ignore_ascii_case(":PROPERTIES:") {
+            in_drawer = true;
+        } else if in_drawer && trimmed.eq_ignore_ascii_case(":END:") {
+            in_drawer = false;
+        } else if in_drawer {
+            if let Some((key, val)) = trimmed.strip_prefix(':').and_then(|p| p.split_once(':')) {
+                lines.push(format!("{indent}{}:: {}", key.to_lowercase(), val.trim()));
+            }
+        } else if let Some((key, val)) = trimmed
+            .strip_prefix("#+")
+            .and_then(|p| p.split_once(':'))
+            .filter(|_| indent.is_empty())
+        {
+            lines.push(format!("{}:: {}", key.to_lowercase(), val.trim()));
+        } else {
+            lines.push(format!("{indent}{line}"));
+        }
+    }
+    lines.join("\n")
+}
+
+#[test]
+fn test_org_to_markdown() {
+    let text = "#+title: Foo\n\n* first\n:PROPERTIES:\n:ID: 6511c1e5-1111-4fd6-9c22-0b8f2ba6a111\n:END:\n** child\nmore";
+    assert_eq!(
+        org_to_markdown(text),
+        "title:: Foo\n\n- first\n  id:: 6511c1e5-1111-4fd6-9c22-0b8f2ba6a111\n  - child\n    more"
+    );
+}
+
+#[test]
+fn test_hoist_block_tags() {
+    let text = "tags:: Topic\n\n- about #rust and `#code` #[[Web Dev]]\n- [[Other]] #[[Topic]] #rust\n  - child #Topic\n- #only-tag\n- issue #42";
+    let mut data = TestData::default();
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "---\ntags: [Topic]\n---\n\n- about #rust and `#code` #Web-Dev\n- [[Other]] #Topic #rust\n    - child #Topic\n\n#only-tag\n\n- issue #42\n"
+    );
+
+    data.config.hoist_block_tags = true;
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "---\ntags: [Topic, rust, Web-Dev, only-tag]\n---\n\n- about #rust and `#code`\n- [[Other]]\n    - child\n\n#only-tag\n\n- issue #42\n"
+    );
+}
+
+#[test]
+fn test_max_depth() {
+    let text = (0..20)
+        .map(|i| format!("{}- level {i}", "  ".repeat(i)))
+        .join("\n");
+    let mut data = TestData {
+        config: Config {
+            max_depth: 5,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let expected = (0..20)
+        .map(|i| format!("{}- level {i}", "    ".repeat(i.min(4))))
+        .join("\n");
+    assert_eq!(convert("Page", &text, &mut data), format!("{expected}\n"));
+    assert_eq!(
+        data.warnings,
+        ["'Page' is nested deeper than 5 levels, the deeper blocks were moved up"]
+    );
+
+    let mut data = TestData::default();
+    let out = convert("Page", &text, &mut data);
+    assert!(out.ends_with(&format!("{}- level 19\n", "    ".repeat(11))));
+    assert_eq!(data.warnings.len(), 1);
+}
+
+#[test]
+fn test_deep_nesting() {
+    let text = (0..10)
+        .map(|i| format!("{}- level {i}", "  ".repeat(i)))
+        .join("\n");
+    let expected = (0..10)
+        .map(|i| format!("{}- level {i}", "    ".repeat(i)))
+        .join("\n");
+    assert_eq!(
+        convert("Page", &text, &mut TestData::default()),
+        format!("{expected}\n")
+    );
+
+    let org = (0..10)
+        .map(|i| format!("{} level {i}", "*".repeat(i + 1)))
+        .join("\n");
+    assert_eq!(org_to_markdown(&org), text);
+}
+
+/// Returns the text of the logseq file `file_name` as markdown, with its
+/// invisible spaces normalized.
+fn page_markdown<'a>(file_name: &Path, text: &'a str) -> Cow<'a, str> {
+    if file_name.extension().is_some_and(|e| e == "org") {
+        Cow::Owned(normalize_spaces(&org_to_markdown(text)).into_owned())
+    } else {
+        normalize_spaces(text)
+    }
+}
+
+/// The lines of the page properties of `text`, before its first block.
+fn page_prop_lines(text: &str) -> impl Iterator<Item = &str> {
+    text.lines()
+        .filter(|l| !l.is_empty())
+        .take_while(|l| !l.trim_start().starts_with('-'))
+}
+
+/// The title of a page without a `title::`, from its file name.
+fn file_title(file_name: &Path) -> String {
+    decode_title(
+        &file_name
+            .file_stem()
+            .unwrap_or(OsStr::new(""))
+            .to_string_lossy(),
+    )
+}
+
+/// Returns the title [`Page::parse`] gives the logseq file `file_name` with
+/// the content `text`, without parsing all of it.
+pub fn page_title(file_name: &Path, text: &str) -> String {
+    let text = page_markdown(file_name, text);
+    let (_, text) = split_frontmatter(&text);
+    let title = page_prop_lines(text).find_map(|l| match parse_prop(l) {
+        Some((Prop::Title, val)) if !val.trim().is_empty() => Some(decode_title(val)),
+        _ => None,
+    });
+    title.unwrap_or_else(|| file_title(file_name))
+}
+
+/// Returns the page title logseq encoded into the file stem `stem`.
+///
+/// Namespace separators are stored as `___` and reserved characters are
+/// percent-encoded (`%3A`), `title::`s sometimes contain them as well.
+pub fn decode_title(stem: &str) -> String {
+    percent_decode(&stem.replace("___", "/"))
+}
+
+/// Decodes the percent-encoded bytes (`%20`) of `s`.
+pub fn percent_decode(s: &str) -> String {
+    let mut bytes = vec![];
+    let mut rest = s.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        let hex = tail
+            .get(..2)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match hex {
+            Some(decoded) if b == b'%' => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(b);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[test]
+fn test_decode_title() {
+    assert_eq!(
+        decode_title("Projects%2FAlpha%20Notes"),
+        "Projects/Alpha Notes"
+    );
+    assert_eq!(decode_title("Foo"), "Foo");
+    assert_eq!(decode_title("Foo___Bar"), "Foo/Bar");
+    assert_eq!(decode_title("Meeting%3A Notes"), "Meeting: Notes");
+    assert_eq!(decode_title("100%"), "100%");
+}
+
+/// Splits the property value `val` on commas outside of `[[...]]` page links.
+fn split_values(val: &str) -> Vec<String> {
+    let mut values = vec![];
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in val.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                values.push(&val[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    values.push(&val[start..]);
+
+    values
+        .into_iter()
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Removes the brackets of the page link `val`.
+fn strip_link(val: &str) -> &str {
+    val.strip_prefix("[[")
+        .and_then(|v| v.strip_suffix("]]"))
+        .map_or(val, str::trim)
+}
+
+#[test]
+fn test_split_values() {
+    assert_eq!(split_values("a, b ,c"), ["a", "b", "c"]);
+    assert_eq!(split_values("[[Foo, Bar]], Baz,"), ["[[Foo, Bar]]", "Baz"]);
+}
+
+/// Quotes `s` if it can't be a plain scalar in a YAML flow sequence.
+fn yaml_str(s: &str) -> String {
+    let special = |c| ",:[]{}#&*!|>'\"%@`".contains(c);
+    if s.is_empty() || s.contains(special) || s.contains(char::is_whitespace) {
+        format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+/// Removes the zero-width spaces and replaces the non-breaking spaces logseq
+/// sometimes leaves around bullets and properties, code blocks are kept as is.
+fn normalize_spaces(text: &str) -> Cow<'_, str> {
+    if !text.contains(['\u{200b}', '\u{a0}']) {
+        return Cow::Borrowed(text);
+    }
+    let mut in_code = false;
+    let text = text
+        .split('\n')
+        .map(|l| {
+            if l.trim_start().trim_start_matches("- ").starts_with("```") {
+                in_code = !in_code;
+                return Cow::Borrowed(l);
+            }
+            match in_code {
+                true => Cow::Borrowed(l),
+                false => Cow::Owned(l.replace('\u{200b}', "").replace('\u{a0}', " ")),
+            }
+        })
+        .join("\n");
+    Cow::Owned(text)
+}
+
+/// Splits a leading `---` fenced YAML frontmatter off `text`.
+///
+/// Returns its top level entries as `(key, entry)`, where the entry contains all
+/// lines up to the next key, and the rest of the text.
+fn split_frontmatter(text: &str) -> (Vec<(String, String)>, &str) {
+    let start = match text.split_inclusive('\n').next() {
+        Some(fence) if fence.trim_end() == "---" => fence.len(),
+        _ => return (vec![], text),
+    };
+    let mut end = start;
+    let mut yaml = None;
+    for line in text[start..].split_inclusive('\n') {
+        end += line.len();
+        if line.trim_end() == "---" {
+            yaml = Some(&text[start..end - line.len()]);
+            break;
+        }
+    }
+    let Some(yaml) = yaml else {
+        return (vec![], text);
+    };
+    let rest = &text[end..];
+
+    let mut entries: Vec<(String, String)> = vec![];
+    for line in yaml.lines().map(str::trim_end).filter(|l| !l.is_empty()) {
+        let key = line.split_once(':').map(|(k, _)| k).filter(|k| {
+            !k.is_empty() && !k.starts_with(char::is_whitespace) && !k.starts_with(['-', '#'])
+        });
+        match (key, entries.last_mut()) {
+            (Some(key), _) => entries.push((key.trim().to_owned(), line.to_owned())),
+            (None, Some((_, entry))) => *entry += &format!("\n{line}"),
+            (None, None) => (),
+        }
+    }
+    (entries, rest)
+}
+
+#[test]
+fn test_page_title() {
+    let title = |name: &str, text: &str| page_title(Path::new(name), text);
+    assert_eq!(title("a___b.md", "- text"), "a/b");
+    assert_eq!(title("a.md", "title:: Foo/Bar\n\n- text"), "Foo/Bar");
+    assert_eq!(title("a.md", "---\nx: y\n---\ntitle:: Foo\n- text"), "Foo");
+    assert_eq!(title("a.org", "#+title: Foo\n* text"), "Foo");
+}
+
+#[test]
+fn test_split_frontmatter() {
+    let (entries, rest) = split_frontmatter("---\ntitle: Foo\ntags:\n  - a\n  - b\n---\n- text");
+    assert_eq!(
+        entries,
+        [
+            ("title".to_owned(), "title: Foo".to_owned()),
+            ("tags".to_owned(), "tags:\n  - a\n  - b".to_owned())
+        ]
+    );
+    assert_eq!(rest, "- text");
+    assert_eq!(split_frontmatter("- text").1, "- text");
+    assert_eq!(split_frontmatter("---\n- text").1, "---\n- text");
+    assert_eq!(split_frontmatter("---\n---\n- text"), (vec![], "- text"));
+}
+
+/// Parses a `key:: value` property with an arbitrary key.
+fn parse_any_prop(line: &str) -> Option<(&str, &str)> {
+    let c = any_prop_re().captures(line.trim())?;
+    Some((
+        c.get(1)?.as_str(),
+        c.get(2).map(|v| v.as_str().trim_end()).unwrap_or_default(),
+    ))
+}
+
+impl Page {
+    pub fn to_string(&self, data: &dyn Data) -> String {
+        let mut blocks = String::new();
+        let mut prev: Option<&Block> = None;
+        for (b, number) in self.blocks.iter().zip(list_numbers(&self.blocks)) {
+            let block = number_list_item(b.to_string(true, data), number);
+            let block = block.trim_end();
+            if block.is_empty() {
+                continue;
+            }
+            if let Some(prev) = prev {
+                // Consecutive list items form one list, everything else is
+                // separated by a blank line.
+                let tight = [prev, b].iter().all(|b| b.is_list_item && !b.is_callout());
+                blocks += if tight { "\n" } else { "\n\n" };
+            }
+            blocks += block;
+            prev = Some(b);
+        }
+        if !blocks.is_empty() {
+            blocks.push('\n');
+        }
+        let heading = format!("# {}", self.title.trim());
+        let title_heading = data.config().title_heading
+            && !self.title.trim().is_empty()
+            && blocks.lines().next() != Some(heading.as_str());
+        if title_heading {
+            let sep = if blocks.is_empty() { "" } else { "\n" };
+            blocks = format!("{heading}\n{sep}{blocks}");
+        }
+
+        let mut frontmatter = String::new();
+        let explicit = |key: &str| self.frontmatter.iter().any(|(k, _)| k == key);
+        if !self.alias.is_empty() && !explicit("aliases") {
+            let alias = self.alias.iter().map(|a| yaml_str(a)).join(", ");
+            frontmatter += &format!("aliases: [{alias}]\n");
+        }
+        if !self.tags.is_empty() && !explicit("tags") {
+            let tags = self.tags.iter().map(|t| yaml_str(t)).join(", ");
+            frontmatter += &format!("tags: [{tags}]\n");
+        }
+        for (key, values) in self.props.iter().filter(|(k, _)| !explicit(k)) {
+            let values = values.iter().map(|v| yaml_str(strip_link(v))).collect_vec();
+            if let [value] = values.as_slice() {
+                frontmatter += &format!("{key}: {value}\n");
+            } else {
+                frontmatter += &format!("{key}: [{}]\n", values.join(", "));
+            }
+        }
+        for (_, entry) in &self.frontmatter {
+            frontmatter += &format!("{entry}\n");
+        }
+        if !frontmatter.is_empty() {
+            let sep = if blocks.is_empty() { "" } else { "\n" };
+            frontmatter = format!("---\n{frontmatter}---\n{sep}");
+        } else if !title_heading && self.blocks.first().is_some_and(Block::is_rule) {
+            // A leading rule would be read as the start of the frontmatter.
+            frontmatter = "\n".to_owned();
+        }
+
+        format!("{frontmatter}{blocks}")
+    }
+
+    pub fn parse(file_name: &Path, text: &str, data: &mut dyn Data) -> Result<Self> {
+        Self::parse_with_anchors(file_name, text, data, &mut HashSet::new())
+    }
+
+    /// Parses the page `text`, `anchors` are the block anchors that are
+    /// already taken, by the other pages merged into the same page.
+    pub fn parse_with_anchors(
+        file_name: &Path,
+        text: &str,
+        data: &mut dyn Data,
+        anchors: &mut HashSet<String>,
+    ) -> Result<Self> {
+        let text = page_markdown(file_name, text);
+        let (frontmatter, text) = split_frontmatter(&text);
+
+        let (title, id, alias, tags, props) = {
+            let mut title = None;
+            let mut id = None;
+            let mut alias = vec![];
+            let mut tags = vec![];
+            let mut props: Vec<(String, Vec<String>)> = vec![];
+
+            for l in page_prop_lines(text) {
+                match parse_prop(l) {
+                    Some((Prop::Alias, val)) => {
+                        alias.extend(split_values(val).iter().map(|v| strip_link(v).to_owned()))
+                    }
+                    Some((Prop::Title, val)) if !val.trim().is_empty() => {
+                        title = Some(decode_title(val))
+                    }
+                    Some((Prop::Id, val)) => id = Some(val.trim().to_owned()),
+                    Some(_) => (),
+                    None => {
+                        let (key, val) = match parse_any_prop(l) {
+                            Some(p) => p,
+                            None => continue,
+                        };
+                        let values = split_values(val);
+                        if key == "icon" {
+                            props.push((key.to_owned(), vec![val.trim().to_owned()]));
+                        } else if key == "public" {
+                            if data.config().public_as_publish && val.trim() == "true" {
+                                props.push(("publish".to_owned(), vec!["true".to_owned()]));
+                            }
+                        } else if key == "aliases" || key == "tags" {
+                            let values = values.iter().map(|v| strip_link(v).to_owned());
+                            match key {
+                                "aliases" => alias.extend(values),
+                                _ => tags.extend(values),
+                            }
+                        } else if let Some((_, v)) = props.iter_mut().find(|(k, _)| k == key) {
+                            v.extend(values);
+                        } else {
+                            props.push((key.to_owned(), values));
+                        }
+                    }
+                }
+            }
+            let title = title.unwrap_or_else(|| file_title(file_name));
+            // Obsidian reports an alias of the page itself as a duplicate.
+            let alias = alias
+                .into_iter()
+                .filter(|a| a.trim().to_lowercase() != title.trim().to_lowercase())
+                .unique()
+                .collect_vec();
+            (title, id, alias, tags, props)
+        };
+        data.page_title(&title);
+        for a in &alias {
+            data.register_alias(a);
+        }
+        for c in block_ref_re().captures_iter(text) {
+            data.referenced_id(&c[1]);
+        }
+        if let Some(logseq_id) = id {
+            data.register_id(&Id {
+                logseq_id,
+                obsdn_id: String::new(),
+                text: format!("[[{}]]", title.trim()),
+                page: data.curr_title().to_owned(),
+            });
+        }
+
+        // Everything before the first block that isn't a page property is a
+        // paragraph, like the ones converted pages have.
+        let lines = text.lines().skip_while(|l| {
+            l.trim().is_empty()
+                || !l.starts_with('-') && (parse_prop(l).is_some() || parse_any_prop(l).is_some())
+        });
+
+        let blocks: Vec<_> = blocks(lines, '-')
+            .into_iter()
+            .map(|l| Block::parse_with_anchors(&l, data, anchors))
+            .try_collect()?;
+
+        Ok(Self {
+            title,
+            alias,
+            tags,
+            props,
+            frontmatter,
+            blocks,
+        })
+    }
+
+    /// Reads and parses the logseq page at `path`.
+    pub fn parse_file(path: &Path, data: &mut dyn Data) -> Result<Self> {
+        Self::parse_file_with_anchors(path, data, &mut HashSet::new())
+    }
+
+    /// Reads and parses the logseq page at `path`, see `parse_with_anchors`.
+    pub fn parse_file_with_anchors(
+        path: &Path,
+        data: &mut dyn Data,
+        anchors: &mut HashSet<String>,
+    ) -> Result<Self> {
+        let context = || anyhow!("Could not read '{}'", path.display());
+        // Decoded separately, so that callers can tell pages that aren't
+        // UTF-8 by their `FromUtf8Error`.
+        let bytes = std::fs::read(path).with_context(context)?;
+        let text = String::from_utf8(bytes).with_context(context)?;
+        Page::parse_with_anchors(path, &text, data, anchors)
+    }
+
+    /// Writes the page to `path`, non-empty pages end with a single newline.
+    pub fn write(&self, path: &Path, data: &dyn Data) -> Result<()> {
+        std::fs::write(path, self.to_string(data))
+            .with_context(|| anyhow!("Could not write '{}'", path.display()))
+    }
+
+    pub fn transform(&mut self, data: &mut dyn Data) {
+        for i in 0..self.blocks.len() {
+            let (prev, rest) = self.blocks.as_mut_slice().split_at_mut(i);
+            let (curr, _rest) = rest.split_at_mut(1);
+            let curr = curr.first_mut().unwrap();
+            curr.transform(None, prev.last(), data);
+        }
+        self.blocks.retain(|b| !b.is_empty(data));
+        if data.config().flatten_empty_groups {
+            self.blocks = flatten_empty_groups(std::mem::take(&mut self.blocks));
+        }
+        let max_depth = data.config().max_depth.max(1);
+        if clamp_depth(&mut self.blocks, 1, max_depth) {
+            let message = format!(
+                "'{}' is nested deeper than {max_depth} levels, the deeper blocks were moved up",
+                data.curr_title()
+            );
+            data.warn(&message);
+        }
+        if data.config().hoist_block_tags {
+            let mut tags = std::mem::take(&mut self.tags);
+            self.blocks
+                .iter_mut()
+                .for_each(|b| hoist_tags(b, &mut tags));
+            self.tags = tags.into_iter().unique().collect();
+        }
+        for p in &data.config().post_processors {
+            p.process(self);
+        }
+    }
+
+    /// Moves all headings `levels` levels down, e.g. below the heading of the
+    /// section the page is merged into. Headings stop at level 6.
+    pub fn demote_headings(&mut self, levels: usize) {
+        fn demote(block: &mut Block, levels: usize) {
+            let level = (block.text.lines().next())
+                .and_then(|l| header_re().captures(l)?.get(1))
+                .map(|l| (l.end(), l.end() - l.start()));
+            if let Some((end, level)) = level {
+                let levels = levels.min(6 - level);
+                block.text.insert_str(end, &"#".repeat(levels));
+            }
+            block.children.iter_mut().for_each(|c| demote(c, levels));
+        }
+        self.blocks.iter_mut().for_each(|b| demote(b, levels));
+    }
+}
+
+/// Replaces the blocks of `blocks` that only group their children by those
+/// children.
+fn flatten_empty_groups(blocks: Vec<Block>) -> Vec<Block> {
+    blocks
+        .into_iter()
+        .flat_map(|b| match b.is_empty_group() {
+            true => b.children,
+            false => vec![b],
+        })
+        .collect()
+}
+
+/// Adds the `#tag`s of `block` and its children to `tags`, and removes those at
+/// the end of blocks that have other text.
+fn hoist_tags(block: &mut Block, tags: &mut Vec<String>) {
+    let masked = mask(&mut block.text, protected_re());
+    tags.extend(
+        block_tag_re()
+            .captures_iter(&block.text)
+            .map(|c| c[1].to_owned()),
+    );
+    if let Some(m) = trailing_tags_re().find(&block.text) {
+        let rest = block.text[..m.start()].trim_start_matches('-').trim();
+        if !rest.is_empty() {
+            block.text.truncate(m.start());
+        }
+    }
+    block.text = unmask(&block.text, &masked);
+    block.children.iter_mut().for_each(|c| hoist_tags(c, tags));
+}
+
+/// Moves the descendants of the `blocks` at `depth` that are deeper than
+/// `max_depth` up to it, after their ancestor at that depth. Returns whether
+/// any block was moved.
+fn clamp_depth(blocks: &mut Vec<Block>, depth: usize, max_depth: usize) -> bool {
+    if depth < max_depth {
+        let mut moved = false;
+        for b in blocks {
+            moved |= clamp_depth(&mut b.children, depth + 1, max_depth);
+        }
+        return moved;
+    }
+
+    fn flatten(mut block: Block, flat: &mut Vec<Block>) {
+        let children = std::mem::take(&mut block.children);
+        flat.push(block);
+        for c in children {
+            flatten(c, flat);
+        }
+    }
+    let moved = blocks.iter().any(|b| !b.children.is_empty());
+    let mut flat = vec![];
+    for b in std::mem::take(blocks) {
+        flatten(b, &mut flat);
+    }
+    *blocks = flat;
+    moved
+}
+
+fn blocks<'a>(lines: impl Iterator<Item = &'a str> + 'a, delim: char) -> Vec<String> {
+    let mut lines = lines.peekable();
+    let mut lines_accu: Option<String> = None;
+
+    let mut result = Vec::<String>::new();
+    let mut after_blank = true;
+    let mut in_paragraph = false;
+    let mut in_code = false;
+
+    while let Some(line) = lines.next() {
+        // Paragraphs between the blocks, e.g. of converted pages, are blocks
+        // of their own, with the list right below them as their children.
+        let is_blank = line.trim().is_empty();
+        let paragraph =
+            !in_code && after_blank && !is_blank && !line.starts_with([delim, ' ', '\t']);
+        if line.starts_with("```") {
+            in_code = !in_code;
+        }
+        let child = in_paragraph && !after_blank;
+        after_blank = is_blank;
+        if paragraph || line.starts_with(delim) && !child {
+            in_paragraph = paragraph;
+            if let Some(accu) = lines_accu.take() {
+                result.push(accu);
+            }
+            lines_accu = Some(String::with_capacity(line.len()));
+        }
+        if let Some(accu) = &mut lines_accu {
+            if !accu.is_empty() {
+                accu.push('\n');
+            }
+            accu.push_str(line);
+        }
+
+        #[allow(clippy::redundant_pattern_matching)]
+        if let None = lines.peek() {
+            let accu = lines_accu.take().unwrap_or_else(|| line.to_string());
+            result.push(accu);
+        }
+    }
+
+    result
+}
+
+#[allow(clippy::ptr_offset_with_cast)]
+unsafe fn union_str<'a>(first: &'a str, last: &'a str) -> &'a str {
+    let len = last.as_ptr().offset(last.len() as isize) as usize - first.as_ptr() as usize;
+    std::str::from_utf8_unchecked(std::slice::from_raw_parts(first.as_ptr(), len))
+}
+
+/// Indents the lines after the first of a list item that aren't indented yet,
+/// so that they stay in the list item.
+///
+/// Code fences are indented as a whole, otherwise their lines would lose their
+/// relative indentation.
+fn indent_continuation_lines(text: &str) -> String {
+    let mut lines = text.split('\n');
+    let first = lines.next().unwrap_or_default();
+    let mut in_code = first.trim_start_matches("- ").starts_with("```");
+    let mut regions = Vec::<Vec<&str>>::new();
+    for l in lines {
+        match regions.last_mut() {
+            Some(region) if in_code => region.push(l),
+            _ => regions.push(vec![l]),
+        }
+        if l.trim_start().starts_with("```") {
+            in_code = !in_code;
+        }
+    }
+
+    regions
+        .into_iter()
+        .fold(first.to_owned(), |mut text, region| {
+            let indent = region.iter().any(|l| !l.is_empty() && !l.starts_with("  "));
+            for l in region {
+                text.push('\n');
+                if indent && !l.is_empty() {
+                    text += "  ";
+                }
+                text += l;
+            }
+            text
+        })
+}
+
+fn trim_start_up_to(n: usize, s: &str) -> &str {
+    let idx = s
+        .char_indices()
+        .take(n)
+        .take_while(|(_, c)| c.is_whitespace())
+        .last()
+        .map(|(i, c)| (i + c.len_utf8()).min(s.len() - 1))
+        .unwrap_or(0);
+    &s[idx..]
+}
+
+#[test]
+fn test_code_in_list_items() {
+    let text = "- [[a]]\n  ```\nfn a() {\n      x\n}\n  ```\n  after\n- ```\n  top\n    nested\n  ```\n  - child\n    ```\n    x\n\n      y\n    ```";
+    let mut data = TestData::default();
+    assert_eq!(
+        convert("Code", text, &mut data),
+        "- [[a]]\n    ```\n  fn a() {\n        x\n  }\n    ```\n  after\n- ```\n  top\n    nested\n  ```\n    - child\n      ```\n      x\n\n        y\n      ```\n"
+    );
+}
+
+#[test]
+fn test_post_processors() {
+    #[derive(Debug)]
+    struct Uppercase;
+    impl PostProcess for Uppercase {
+        fn process(&self, page: &mut Page) {
+            page.title = page.title.to_uppercase();
+            page.blocks
+                .iter_mut()
+                .for_each(|b| b.text = b.text.to_uppercase());
+        }
+    }
+
+    let text = "- first   \n  second\t\n- \n  - child  ";
+    let mut data = TestData {
+        config: Config {
+            post_processors: vec![
+                Arc::new(crate::postprocess::TrimTrailingWhitespace),
+                Arc::new(Uppercase),
+            ],
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut page = Page::parse(Path::new("Page.md"), text, &mut data).unwrap();
+    page.transform(&mut data);
+    assert_eq!(page.title, "PAGE");
+    assert_eq!(page.to_string(&data), "- FIRST\n  SECOND\n-\n    - child\n");
+
+    // The indent of children doesn't add whitespace to their empty lines.
+    let text = "- parent\n  - ```\n    a\n\n    b\n    ```";
+    let mut page = Page::parse(Path::new("Page.md"), text, &mut data).unwrap();
+    page.transform(&mut data);
+    let out = page.to_string(&data);
+    assert!(out.lines().all(|l| l.trim_end() == l), "{out:?}");
+}
+
+#[test]
+fn test_empty_groups() {
+    let text = "- before\n- \n  - a\n  - b\n- parent\n  -\n    - c\n    - d";
+    let mut data = TestData::default();
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "- before\n-\n    - a\n    - b\n- parent\n    -\n        - c\n        - d\n"
+    );
+    data.config.flatten_empty_groups = true;
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "- before\n- a\n- b\n- parent\n    - c\n    - d\n"
+    );
+}
+
+#[test]
+fn test_convert_task() {
+    let mut data = TestData::default();
+    let out = convert(
+        "Tasks",
+        "- DONE write tests\n  id:: 6511c1e5-1111-4fd6-9c22-0b8f2ba6a111\n- some TODO item",
+        &mut data,
+    );
+    let anchor = &data.refs["6511c1e5-1111-4fd6-9c22-0b8f2ba6a111"].id[1..];
+    assert_eq!(
+        out,
+        format!("- [x] write tests {anchor}\n- some TODO item\n")
+    );
+
+    let mut block = Block {
+        text: "TODO write\nmore tests".to_owned(),
+        ..Default::default()
+    };
+    block.transform(None, None, &mut data);
+    assert!(block.is_list_item);
+    assert_eq!(block.text, "- [ ] write\n  more tests");
+
+    data.config.strike_done = true;
+    let mut block = Block {
+        text: "DONE [#A] shipped".to_owned(),
+        ..Default::default()
+    };
+    block.transform(None, None, &mut data);
+    assert!(block.is_list_item);
+    assert_eq!(block.text, "- [x] ~~shipped~~ 🔺");
+}
+
+#[test]
+fn test_completion_date() {
+    let text = "- DONE a\n  :LOGBOOK:\n  CLOCK: [2023-10-04 Wed 10:00]--[2023-10-04 Wed 11:00] =>  01:00:00\n  CLOCK: [2023-10-05 Thu 09:00]--[2023-10-05 Thu 10:00] =>  01:00:00\n  :END:\n- DONE b\n  :LOGBOOK:\n  :COMPLETED: [2023-10-06 Fri 12:00]\n  :END:\n- DONE c\n- TODO d\n  :LOGBOOK:\n  CLOCK: [2023-10-05 Thu 09:00]\n  :END:";
+    let mut data = TestData::default();
+    assert_eq!(
+        convert("Tasks", text, &mut data),
+        "- [x] a ✅ 2023-10-05\n- [x] b ✅ 2023-10-06\n- [x] c\n- [ ] d\n"
+    );
+}
+
+#[test]
+fn test_rich_task_states() {
+    let text = "- DOING a\n- NOW b\n- WAITING c\n- TODO d";
+    let mut data = TestData::default();
+    assert_eq!(
+        convert("Tasks", text, &mut data),
+        "- [ ] a\n- [ ] b\n- [ ] c\n- [ ] d\n"
+    );
+    data.config.rich_task_states = true;
+    assert_eq!(
+        convert("Tasks", text, &mut data),
+        "- [/] a\n- [/] b\n- [?] c\n- [ ] d\n"
+    );
+}
+
+#[test]
+fn test_task_priorities() {
+    let text = "- TODO [#A] a\n- LATER [#B] b\n  SCHEDULED: <2023-10-05 Thu>\n- DONE [#C] c\n- TODO d [#A]";
+    let mut data = TestData::default();
+    assert_eq!(
+        convert("Tasks", text, &mut data),
+        "- [ ] a 🔺\n- [ ] b 🔼 ⏳ 2023-10-05\n- [x] c 🔽\n- [ ] d [#A]\n"
+    );
+    data.config.strike_done = true;
+    assert_eq!(
+        convert("Tasks", "- DONE [#A] a", &mut data),
+        "- [x] ~~a~~ 🔺\n"
+    );
+}
+
+#[test]
+fn test_config() {
+    let text = "- DONE release {{cloze v1}}\n  SCHEDULED: <2023-10-05 Thu>";
+    assert_eq!(
+        convert("Tasks", text, &mut TestData::default()),
+        "- [x] release ==v1== ⏳ 2023-10-05\n"
+    );
+    let mut data = TestData {
+        config: Config {
+            cloze_style: ClozeStyle::Braces,
+            strike_done: true,
+            task_dates: false,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    assert_eq!(
+        convert("Tasks", text, &mut data),
+        "- [x] ~~release {{v1}}~~\n"
+    );
+}
+
+#[test]
+fn test_strike_done() {
+    let mut data = TestData {
+        config: Config {
+            strike_done: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let out = convert(
+        "Tasks",
+        "- DONE write tests\n  id:: 6511c1e5-1111-4fd6-9c22-0b8f2ba6a111\n- TODO more",
+        &mut data,
+    );
+    let anchor = &data.refs["6511c1e5-1111-4fd6-9c22-0b8f2ba6a111"].id[1..];
+    assert_eq!(out, format!("- [x] ~~write tests~~ {anchor}\n- [ ] more\n"));
+}
+
+#[test]
+fn test_existing_checkboxes() {
+    let mut data = TestData {
+        config: Config {
+            strike_done: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let text = "- TODO keyword task\n- [x] DONE already checked\n- [ ] TODO [#A] open\n  - [x] **bold** child\n- [x] #tag done";
+    assert_eq!(
+        convert("Tasks", text, &mut data),
+        "- [ ] keyword task\n- [x] DONE already checked\n- [ ] TODO [#A] open\n    - [x] **bold** child\n- [x] #tag done\n"
+    );
+}
+
+#[test]
+fn test_convert_task_dates() {
+    let text =
+        "- TODO release\n  SCHEDULED: <2023-10-05 Thu 10:00 .+1d>\n  DEADLINE: <2023-10-06 Fri>";
+    let mut data = TestData::default();
+    assert_eq!(
+        convert("Tasks", text, &mut data),
+        "- [ ] release ⏳ 2023-10-05 📅 2023-10-06\n"
+    );
+
+    data.config.task_dates = false;
+    assert_eq!(convert("Tasks", text, &mut data), "- [ ] release\n");
+}
+
+#[test]
+fn test_page_props() {
+    let text = "alias:: Other\nauthor:: Me\nstatus:: draft, review\naliases:: More\n\n- text";
+    assert_eq!(
+        convert("Page", text, &mut TestData::default()),
+        "---\naliases: [Other, More]\nauthor: Me\nstatus: [draft, review]\n---\n\n- text\n"
+    );
+    assert_eq!(
+        convert("Page", "- text", &mut TestData::default()),
+        "- text\n"
+    );
+}
+
+#[test]
+fn test_quoted_props() {
+    let text =
+        "note:: Note: x\nstate:: #done\nrelated:: [[A]], [[B]]\nowner:: [[Smith, John]]\n\n- text";
+    assert_eq!(
+        convert("Page", text, &mut TestData::default()),
+        "---\nnote: \"Note: x\"\nstate: \"#done\"\nrelated: [A, B]\nowner: \"Smith, John\"\n---\n\n- text\n"
+    );
+}
+
+#[test]
+fn test_icon_and_public() {
+    let mut data = TestData::default();
+    let text = "icon:: 📘\npublic:: true\n\n- text";
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "---\nicon: 📘\n---\n\n- text\n"
+    );
+    assert_eq!(
+        convert("Page", "icon:: 📘 #1\n\n- text", &mut data),
+        "---\nicon: \"📘 #1\"\n---\n\n- text\n"
+    );
+    data.config.public_as_publish = true;
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "---\nicon: 📘\npublish: true\n---\n\n- text\n"
+    );
+    assert_eq!(
+        convert("Page", "public:: false\n\n- text", &mut data),
+        "- text\n"
+    );
+}
+
+#[test]
+fn test_encoded_titles() {
+    let mut data = TestData::default();
+    convert("Projects%2FAlpha", "- text", &mut data);
+    assert_eq!(data.title, "Projects/Alpha");
+    convert("Projects___Alpha", "- text", &mut data);
+    assert_eq!(data.title, "Projects/Alpha");
+    convert("Foo", "title:: Projects%2FAlpha\n\n- text", &mut data);
+    assert_eq!(data.title, "Projects/Alpha");
+}
+
+#[test]
+fn test_yaml_frontmatter() {
+    let text = "---\ntags: [a, b]\ncssclasses:\n  - wide\n---\nalias:: Other\n\n- text";
+    assert_eq!(
+        convert("Page", text, &mut TestData::default()),
+        "---\naliases: [Other]\ntags: [a, b]\ncssclasses:\n  - wide\n---\n\n- text\n"
+    );
+    let text = "---\naliases: [Mine]\n---\nalias:: Other\nstatus:: draft\n\n- text";
+    assert_eq!(
+        convert("Page", text, &mut TestData::default()),
+        "---\nstatus: draft\naliases: [Mine]\n---\n\n- text\n"
+    );
+}
+
+#[test]
+fn test_self_alias() {
+    let text = "title:: My Page\nalias:: my page , Other\n\n- text";
+    assert_eq!(
+        convert("Page", text, &mut TestData::default()),
+        "---\naliases: [Other]\n---\n\n- text\n"
+    );
+    assert_eq!(
+        convert("Foo", "alias:: FOO\n\n- text", &mut TestData::default()),
+        "- text\n"
+    );
+}
+
+#[test]
+fn test_split_aliases() {
+    let expected = "---\naliases: [a, b, c]\n---\n\n- text\n";
+    for text in [
+        "alias:: a, b, c\n\n- text",
+        "alias:: a\nalias:: b\nalias:: c\n\n- text",
+        "alias:: a, b\nalias:: [[b]], c\n\n- text",
+        "alias:: [[a]], b, [[ c ]]\n\n- text",
+    ] {
+        assert_eq!(convert("Page", text, &mut TestData::default()), expected);
+    }
+}
+
+#[test]
+fn test_quoted_aliases() {
+    let text =
+        "alias:: [[Smith, John]]\naliases:: [[Note: \"draft\"]]\ntags:: Foo, [[a, b]]\n\n- text";
+    assert_eq!(
+        convert("Page", text, &mut TestData::default()),
+        "---\naliases: [\"Smith, John\", \"Note: \\\"draft\\\"\"]\ntags: [Foo, \"a, b\"]\n---\n\n- text\n"
+    );
+}
+
+#[test]
+fn test_page_tags() {
+    let text = "tags:: [[Rust]], [[Open Source]], cli\n- text";
+    assert_eq!(
+        convert("Page", text, &mut TestData::default()),
+        "---\ntags: [Rust, \"Open Source\", cli]\n---\n\n- text\n"
+    );
+}
+
+#[test]
+fn test_colored_highlights() {
+    let text = "- ^^plain^^ and [[$red]]^^red^^ or [[$green]]==green== costs $5";
+    assert_eq!(
+        convert("Page", text, &mut TestData::default()),
+        "- ==plain== and ==red== or ==green== costs $5\n"
+    );
+    let mut data = TestData::default();
+    data.config.html_highlights = true;
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "- ==plain== and <mark style=\"background: red\">red</mark> or <mark style=\"background: green\">green</mark> costs $5\n"
+    );
+    assert_eq!(
+        convert(
+            "Page",
+            "- [[$red]]^^x^^ where f=$a^^b^^c$",
+            &mut TestData::default()
+        ),
+        "- ==x== where f=$a^^b^^c$\n"
+    );
+}
+
+#[test]
+fn test_highlight() {
+    let text =
+        "- some ^^important^^ text\n- ^^marked^^\n  id:: 6511c1e5-1111-4fd6-9c22-0b8f2ba6a111";
+    let mut data = TestData::default();
+    let out = convert("Page", text, &mut data);
+    let anchor = &data.refs["6511c1e5-1111-4fd6-9c22-0b8f2ba6a111"].id[1..];
+    assert_eq!(
+        out,
+        format!("- some ==important== text\n- ==marked== {anchor}\n")
+    );
+}
+
+#[test]
+fn test_page_embed() {
+    let text = "- {{embed [[Some Page]]}}\n- {{embed [[Über uns]]}} and {{embed ((6511c1e5-1111-4fd6-9c22-0b8f2ba6a111))}}";
+    assert_eq!(
+        convert("Page", text, &mut TestData::default()),
+        "![[Some Page]]\n\n- ![[Über uns]] and {{embed ((6511c1e5-1111-4fd6-9c22-0b8f2ba6a111))}}\n"
+    );
+}
+
+#[test]
+fn test_page_heading_embed() {
+    let text = "- {{embed [[Some Page#Setup]]}}\n- see {{embed [[Some Page]]}} and {{embed [[Über uns#Team]]}}";
+    assert_eq!(
+        convert("Page", text, &mut TestData::default()),
+        "![[Some Page#Setup]]\n\n- see ![[Some Page]] and ![[Über uns#Team]]\n"
+    );
+}
+
+#[test]
+fn test_annotated_embeds() {
+    let id = "6511c1e5-1111-4fd6-9c22-0b8f2ba6a111";
+    let mut data = TestData::default();
+    convert("Other", &format!("- block\n  id:: {id}"), &mut data);
+    let anchor = data.refs[id].id.clone();
+    let text = format!("- {{{{embed (({id})) width=300}}}}\n- {{{{embed [[diagram.png]] :width 400px}}}}\n- {{{{embed [[Some Page]] zoom=2}}}}");
+    assert_eq!(
+        convert("Page", &text, &mut data),
+        format!("![[Other{anchor}]]\n\n![[diagram.png|400]]\n\n![[Some Page]]\n")
+    );
+}
+
+#[test]
+fn test_media_macros() {
+    let mut data = TestData::default();
+    let text = "- {{youtube https://youtu.be/abc}}\n- {{video [[https://example.com/a.mp4]]}}";
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "- <https://youtu.be/abc> ![](https://youtu.be/abc)\n- ![](https://example.com/a.mp4)\n"
+    );
+    assert!(data.assets.is_empty());
+    assert!(data.warnings.is_empty());
+}
+
+#[test]
+fn test_dynamic_macros() {
+    let text =
+        "- {{namespace Projects}}\n- {{namespace [[Projects/Alpha]]}}\n- tags: {{page-tags}}";
+    let mut data = TestData::default();
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "- %% logseq dynamic: namespace Projects %%\n- %% logseq dynamic: namespace Projects/Alpha %%\n- tags: %% logseq dynamic: page-tags %%\n"
+    );
+    data.config.drop_dynamic = true;
+    assert_eq!(convert("Page", text, &mut data), "- tags:\n");
+}
+
+#[test]
+fn test_function_macros() {
+    let text = "- total: {{function (sum :estimate)}}\n- {{sum 1, 2, 3.5}} of {{count 4 5}}\n- {{sum :estimate}}";
+    let mut data = TestData::default();
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "- total: %% logseq dynamic: function (sum :estimate) %%\n- 6.5 of 2\n- %% logseq dynamic: sum :estimate %%\n"
+    );
+    assert_eq!(
+        convert("Page", "- {{sum 0.1, 0.2}} and {{sum 1.50 2}}", &mut data),
+        "- 0.3 and 3.50\n"
+    );
+    data.config.drop_dynamic = true;
+    assert_eq!(
+        convert("Page", "- total: {{function (sum :estimate)}}", &mut data),
+        "- total:\n"
+    );
+}
+
+#[test]
+fn test_macros() {
+    let mut data = TestData::default();
+    let text = "- {{tweet https://twitter.com/a/status/1}}\n- {{bilibili BV1xx411c7mD}}\n- {{poll a, b}} and {{cards}}\n- {{embed [[Other]]}} {{query (todo now)}} {{cloze Paris}}";
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "- [Tweet](https://twitter.com/a/status/1)\n- [Bilibili](https://www.bilibili.com/video/BV1xx411c7mD)\n- %% unsupported macro: poll a, b %% and %% unsupported macro: cards %%\n- ![[Other]] %% logseq query: (todo now) %% ==Paris==\n"
+    );
+    data.config.cloze_style = ClozeStyle::Braces;
+    assert_eq!(
+        convert("Page", "- {{cloze Paris}}", &mut data),
+        "- {{Paris}}\n"
+    );
+}
+
+#[test]
+fn test_cloze() {
+    let mut data = TestData::default();
+    let text = "- {{cloze Paris}} is the capital of {{cloze France}}";
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "- ==Paris== is the capital of ==France==\n"
+    );
+    data.config.cloze_style = ClozeStyle::Braces;
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "- {{Paris}} is the capital of {{France}}\n"
+    );
+}
+
+#[test]
+fn test_card() {
+    let text = "- What is the capital of France? #card\n  - Paris";
+    assert_eq!(
+        convert("Page", text, &mut TestData::default()),
+        "What is the capital of France?\n?\nParis\n"
+    );
+}
+
+#[test]
+fn test_queries() {
+    let mut data = TestData::default();
+    let text =
+        "- {{query (and [[a]] [[b]])}}\n- #+BEGIN_QUERY\n  {:query [:find ?b]}\n  #+END_QUERY";
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "- %% logseq query: (and [[a]] [[b]]) %%\n\n%% logseq query:\n{:query [:find ?b]}\n%%\n"
+    );
+    data.config.drop_queries = true;
+    assert_eq!(convert("Page", text, &mut data), "");
+}
+
+#[test]
+fn test_org_blocks() {
+    let mut data = TestData::default();
+    assert_eq!(
+        convert(
+            "Page",
+            "- #+BEGIN_QUOTE\n  To be, or not to be.\n\n  Hamlet\n  #+END_QUOTE",
+            &mut data
+        ),
+        "> To be, or not to be.\n>\n> Hamlet\n"
+    );
+    assert_eq!(
+        convert(
+            "Page",
+            "- Remember\n  #+BEGIN_NOTE\n  Water the plants\n  #+END_NOTE",
+            &mut data
+        ),
+        "- Remember\n  > [!note]\n  > Water the plants\n"
+    );
+    let text = "- #+BEGIN_CENTER\n  text\n  #+END_CENTER";
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "#+BEGIN_CENTER\ntext\n#+END_CENTER\n"
+    );
+}
+
+#[test]
+fn test_renderers() {
+    let mut data = TestData::default();
+    let text = "- {{renderer :tocgen}}\n- a {{renderer :todomaster, {:a 1}, b}} b";
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "- %% logseq renderer: :tocgen %%\n- a %% logseq renderer: :todomaster, {:a 1}, b %% b\n"
+    );
+    data.config.drop_renderers = true;
+    assert_eq!(convert("Page", text, &mut data), "- a  b\n");
+}
+
+#[test]
+fn test_rule() {
+    let mut data = TestData::default();
+    assert_eq!(
+        convert("Page", "- a\n- ---\n- b", &mut data),
+        "- a\n\n---\n\n- b\n"
+    );
+    assert_eq!(
+        convert("Page", "- a\n  - ---\n  - b", &mut data),
+        "- a\n\n  ---\n\n    - b\n"
+    );
+    assert_eq!(convert("Page", "- ---\n- b", &mut data), "\n---\n\n- b\n");
+}
+
+#[test]
+fn test_footnote() {
+    assert_eq!(
+        convert(
+            "Page",
+            "- Some claim[^1]\n- [^1]: The source",
+            &mut TestData::default()
+        ),
+        "- Some claim[^1]\n\n[^1]: The source\n"
+    );
+}
+
+#[test]
+fn test_keep_bold_headers_as_list() {
+    let mut data = TestData::default();
+    let text = "- **Bold**\n  - child";
+    assert_eq!(convert("Page", text, &mut data), "**Bold**\n- child\n");
+    data.config.keep_bold_headers_as_list = true;
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "- **Bold**\n    - child\n"
+    );
+}
+
+#[test]
+fn test_trailing_newline() {
+    for text in [
+        "- a",
+        "- a\n  - b\n    - c",
+        "- a\n- b\n\n\n",
+        "- ```\n  code\n  ```",
+        "- a #.v-self-border",
+        "alias:: Other",
+        "alias:: Other\n\n- a\n\n",
+        "- ---",
+    ] {
+        let output = convert("Page", text, &mut TestData::default());
+        assert!(
+            output.ends_with('\n') && !output.ends_with("\n\n"),
+            "{text:?} became {output:?}"
+        );
+    }
+    assert_eq!(convert("Page", "", &mut TestData::default()), "");
+}
+
+#[test]
+fn test_file_round_trip() {
+    let dir = std::env::temp_dir().join("logseq2obsdn-file-round-trip");
+    std::fs::create_dir_all(&dir).unwrap();
+    let fixture = dir.join("Fixture.md");
+    std::fs::write(&fixture, "alias:: Other\n\n- TODO a\n  - ^^b^^\n").unwrap();
+
+    let mut data = TestData::default();
+    let mut page = Page::parse_file(&fixture, &mut data).unwrap();
+    assert_eq!(data.title, "Fixture");
+    page.transform(&mut data);
+    let out = dir.join("out.md");
+    page.write(&out, &data).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&out).unwrap(),
+        "---\naliases: [Other]\n---\n\n- [ ] a\n    - ==b==\n"
+    );
+
+    let err = Page::parse_file(&dir.join("Missing.md"), &mut data).unwrap_err();
+    assert!(err.to_string().contains("Missing.md"));
+}
+
+#[test]
+fn test_trailing_ids() {
+    let mut data = TestData::default();
+    let text = "- Hello world id:: 6511c1e5-1111-4fd6-9c22-0b8f2ba6a111\n- Use `a id:: b` and key:: value\n- see ((6511c1e5-1111-4fd6-9c22-0b8f2ba6a111))";
+    let out = convert("Page", text, &mut data);
+    let anchor = &data.refs["6511c1e5-1111-4fd6-9c22-0b8f2ba6a111"].id;
+    assert_eq!(
+        out,
+        format!(
+            "- Hello world {}\n- Use `a id:: b` and key:: value\n- see [[{anchor}]]\n",
+            &anchor[1..]
+        )
+    );
+}
+
+#[test]
+fn test_replacements() {
+    let mut data = TestData::default();
+    data.config.replacements = vec![
+        (Regex::new(r"#flashcard\b").unwrap(), "#card".to_owned()),
+        (Regex::new(r"\bTODO\((\w+)\)").unwrap(), "@$1".to_owned()),
+    ];
+    let text =
+        "- What is 6x7? #flashcard\n  - 42\n- ask TODO(anna) and TODO(ben), not `#flashcard`";
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "What is 6x7?\n?\n42\n\n- ask @anna and @ben, not `#flashcard`\n"
+    );
+}
+
+#[test]
+fn test_idempotent() {
+    let text = "- TODO write tests\n  SCHEDULED: <2023-10-05 Thu>\n- DONE [#A] ship it\n- some ^^important^^ and ==marked== text #tag #[[two words]]\n- see [[Other Page]] and [link](https://example.com)\n- ![img](../assets/a.png)\n- **Bold header**\n  - child\n    - grandchild\n- # Heading\n- quote\n  > quoted\n- $x^2$ and `code`\n- ```rust\n  let a = 1;\n\n  let b = 2;\n  ```\n- a\n  id:: 6511c1e5-1111-4fd6-9c22-0b8f2ba6a111\n- ref ((6511c1e5-1111-4fd6-9c22-0b8f2ba6a111))\n- {{cloze answer}} and {{video https://x.com/a.mp4}}\n- **Definition** #.v-self-border\n  the meaning\n\n  of it\n- #card question\n  - answer";
+    let mut data = TestData::default();
+    let once = convert("Page", text, &mut data);
+    assert_eq!(convert("Page", &once, &mut data), once);
+}
+
+#[test]
+fn test_degenerate_list_items() {
+    let mut data = TestData::default();
+    for body in ["-", "- ", "-   "] {
+        assert_eq!(convert("Page", body, &mut data), "", "{body:?}");
+        let text = format!("- a\n{body}\n  collapsed:: true\n- b");
+        assert_eq!(convert("Page", &text, &mut data), "- a\n- b\n", "{body:?}");
+        let text = format!("{body}\n  - child");
+        assert_eq!(
+            convert("Page", &text, &mut data),
+            "-\n    - child\n",
+            "{body:?}"
+        );
+        let mut block = Block::parse(body, &mut data).unwrap();
+        block.set_list_item(false);
+        block.set_list_item(true);
+        block.transform(None, None, &mut data);
+        block.to_string(true, &data);
+    }
+    assert_eq!(list_item_to_normal("-"), "");
+    assert_eq!(list_item_to_normal("- "), "");
+    assert_eq!(normal_to_list_item(""), "- ");
+}
+
+#[test]
+fn test_property_only_blocks() {
+    let mut data = TestData::default();
+    let text = "- a\n- collapsed:: true\n- b\n  - foo:: bar\n  - id:: 6511c1e5-1111-4fd6-9c22-0b8f2ba6a111\n  - c";
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "- a\n- b\n    - foo:: bar\n    - ^7d01a07b497eaa8\n    - c\n"
+    );
+    // Unless their anchor isn't written, blocks with an id stay for the
+    // references to them.
+    data.referenced = Some(vec![]);
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "- a\n- b\n    - foo:: bar\n    - c\n"
+    );
+    // Blocks with children stay.
+    assert_eq!(
+        convert("Page", "- collapsed:: true\n  - child", &mut data),
+        "-\n    - child\n"
+    );
+}
+
+#[test]
+fn test_tracing() {
+    let text = "- TODO ^^a^^ #[[b c]]\n  - `^^code^^`";
+    let plain = convert("Page", text, &mut TestData::default());
+    let mut data = TestData {
+        traces: Some(vec![]),
+        ..Default::default()
+    };
+    assert_eq!(convert("Page", text, &mut data), plain);
+    assert_eq!(data.traces.unwrap(), ["tasks", "highlights", "hashtags"]);
+}
+
+#[test]
+fn test_block_spacing() {
+    let mut data = TestData::default();
+    assert_eq!(
+        convert("Page", "- # One\n- # Two", &mut data),
+        "# One\n\n# Two\n"
+    );
+    assert_eq!(convert("Page", "- a\n- b", &mut data), "- a\n- b\n");
+    assert_eq!(
+        convert("Page", "- a\n- # Two\n- b", &mut data),
+        "- a\n\n# Two\n\n- b\n"
+    );
+}
+
+#[test]
+fn test_interleaved_props() {
+    let mut data = TestData::default();
+    let text = "- line1\n  id:: 6511c1e5-1111-4fd6-9c22-0b8f2ba6a111\n  line2\n  collapsed:: true\n  line3";
+    let out = convert("Page", text, &mut data);
+    let anchor = &data.refs["6511c1e5-1111-4fd6-9c22-0b8f2ba6a111"].id[1..];
+    assert_eq!(out, format!("- line1\n  line2\n  line3 {anchor}\n"));
+}
+
+#[test]
+fn test_block_props() {
+    let mut data = TestData::default();
+    let text = "- task\n  priority:: high\n  ```\n  a:: b\n  ```";
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "- task\n  priority:: high\n  ```\n  a:: b\n  ```\n"
+    );
+    data.config.block_props = BlockProps::Drop;
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "- task\n  ```\n  a:: b\n  ```\n"
+    );
+}
+
+#[test]
+fn test_latex_math() {
+    let mut data = TestData::default();
+    assert_eq!(
+        convert("Page", r"- where \( x^2 \) is positive", &mut data),
+        "- where $x^2$ is positive\n"
+    );
+    assert_eq!(
+        convert("Page", "- \\[\n\\sum_i x_i\n\\]", &mut data),
+        "$$\n\\sum_i x_i\n$$\n"
+    );
+    assert_eq!(
+        convert("Page", r"- \(escaped and `\(code\)`", &mut data),
+        "- \\(escaped and `\\(code\\)`\n"
+    );
+}
+
+#[test]
+fn test_table() {
+    let text =
+        "- Prices\n  - | Item | Price |\n    | --- | ---: |\n    | Tea | 2 |\n    | Cake | 3 |";
+    assert_eq!(
+        convert("Page", text, &mut TestData::default()),
+        "- Prices\n\n  | Item | Price |\n  | --- | ---: |\n  | Tea | 2 |\n  | Cake | 3 |\n"
+    );
+}
+
+#[test]
+fn test_inline_refs() {
+    let mut data = TestData {
+        config: Config {
+            inline_refs: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    convert(
+        "Other",
+        "- The **answer** is 42\n  id:: 6511c1e5-1111-4fd6-9c22-0b8f2ba6a111",
+        &mut data,
+    );
+    assert_eq!(
+        convert(
+            "Page",
+            "- Remember: ((6511c1e5-1111-4fd6-9c22-0b8f2ba6a111))",
+            &mut data
+        ),
+        "- Remember: The **answer** is 42\n"
+    );
+
+    convert(
+        "Other",
+        "- TODO ^^ship^^ it\n  id:: 6511c1e5-2222-4fd6-9c22-0b8f2ba6a222",
+        &mut data,
+    );
+    assert_eq!(
+        convert(
+            "Page",
+            "- Next: ((6511c1e5-2222-4fd6-9c22-0b8f2ba6a222))",
+            &mut data
+        ),
+        "- Next: [ ] ==ship== it\n"
+    );
+}
+
+#[test]
+fn test_circular_refs() {
+    let text = "- a {{embed ((6511c1e5-2222-4fd6-9c22-0b8f2ba6a111))}}\n  id:: 6511c1e5-1111-4fd6-9c22-0b8f2ba6a111\n- b ((6511c1e5-1111-4fd6-9c22-0b8f2ba6a111))\n  id:: 6511c1e5-2222-4fd6-9c22-0b8f2ba6a111";
+    for inline_refs in [false, true] {
+        let mut data = TestData {
+            config: Config {
+                inline_refs,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let out = convert("Page", text, &mut data);
+        let a = &data.refs["6511c1e5-1111-4fd6-9c22-0b8f2ba6a111"].id;
+        let b = &data.refs["6511c1e5-2222-4fd6-9c22-0b8f2ba6a111"].id;
+        assert_eq!(
+            out,
+            format!("- a ![[{b}]] {}\n- b [[{a}]] {}\n", &a[1..], &b[1..])
+        );
+        assert_eq!(data.warnings.len(), 2, "{:?}", data.warnings);
+        assert!(data.warnings[0].starts_with("Circular block reference"));
+    }
+
+    let mut data = TestData {
+        config: Config {
+            inline_refs: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let text = "- a\n  id:: 6511c1e5-1111-4fd6-9c22-0b8f2ba6a111\n- b ((6511c1e5-1111-4fd6-9c22-0b8f2ba6a111))\n  id:: 6511c1e5-2222-4fd6-9c22-0b8f2ba6a111\n- c ((6511c1e5-2222-4fd6-9c22-0b8f2ba6a111))";
+    let out = convert("Page", text, &mut data);
+    assert!(out.ends_with("- c b a\n"), "{out}");
+    assert!(data.warnings.is_empty(), "{:?}", data.warnings);
+}
+
+#[test]
+fn test_top_level_headers() {
+    let mut data = TestData::default();
+    let out = |text: &str, data: &mut TestData| convert("Page", text, data);
+    assert_eq!(out("- # Title", &mut data), "# Title\n");
+    assert_eq!(out("- ## Title", &mut data), "# Title\n");
+    assert_eq!(out("- #### Title", &mut data), "### Title\n");
+    assert_eq!(
+        out("- ### Title\n  - ### Child", &mut data),
+        "## Title\n### Child\n"
+    );
+}
+
+#[test]
+fn test_demote_headings() {
+    let mut data = TestData::default();
+    let mut page = Page::parse(
+        Path::new("Page.md"),
+        "- ## Title\n  - ### Child\n    - ###### Deep\n- text",
+        &mut data,
+    )
+    .unwrap();
+    page.transform(&mut data);
+    page.demote_headings(2);
+    assert_eq!(
+        page.to_string(&data),
+        "### Title\n##### Child\n###### Deep\n\n- text\n"
+    );
+}
+
+#[test]
+fn test_header_ref() {
+    let mut data = TestData::default();
+    convert(
+        "Other",
+        "- ## Setup: [[Linux]] Notes  v2\n  id:: 6511c1e5-1111-4fd6-9c22-0b8f2ba6a111",
+        &mut data,
+    );
+    assert_eq!(
+        convert(
+            "Page",
+            "- see ((6511c1e5-1111-4fd6-9c22-0b8f2ba6a111))",
+            &mut data
+        ),
+        "- see [[Other#Setup Linux Notes  v2]]\n"
+    );
+}
+
+#[test]
+fn test_page_id() {
+    let mut data = TestData::default();
+    assert_eq!(
+        convert(
+            "Other",
+            "title:: Other\nid:: 6511c1e5-1111-4fd6-9c22-0b8f2ba6a111",
+            &mut data
+        ),
+        ""
+    );
+    assert_eq!(data.refs["6511c1e5-1111-4fd6-9c22-0b8f2ba6a111"].id, "");
+    assert_eq!(
+        convert(
+            "Page",
+            "- see ((6511c1e5-1111-4fd6-9c22-0b8f2ba6a111))",
+            &mut data
+        ),
+        "- see [[Other]]\n"
+    );
+}
+
+#[test]
+fn test_anchors_match_output() {
+    let mut data = TestData::default();
+    let text = "- ## A ^^key^^ idea\n  id:: 6511c1e5-1111-4fd6-9c22-0b8f2ba6a111\n- TODO write ^^this^^\n  id:: 6511c1e5-2222-4fd6-9c22-0b8f2ba6a222";
+    // Like `--extract-ids`, which only parses the pages.
+    Page::parse(Path::new("Page.md"), text, &mut data).unwrap();
+    let registered = data.refs.clone();
+    let output = convert("Page", text, &mut TestData::default());
+    let header = &registered["6511c1e5-1111-4fd6-9c22-0b8f2ba6a111"].id;
+    assert_eq!(header, "#A ==key== idea");
+    assert!(output.contains(&format!("# {}\n", &header[1..])));
+    let block = &registered["6511c1e5-2222-4fd6-9c22-0b8f2ba6a222"].id;
+    assert!(output.contains(&format!(" {}\n", &block[1..])));
+}
+
+#[test]
+fn test_block_embeds() {
+    let mut data = TestData::default();
+    convert(
+        "Other",
+        "- ## Setup\n  id:: 6511c1e5-1111-4fd6-9c22-0b8f2ba6a111\n- plain\n  id:: 6511c1e5-2222-4fd6-9c22-0b8f2ba6a111",
+        &mut data,
+    );
+    let anchor = data.refs["6511c1e5-2222-4fd6-9c22-0b8f2ba6a111"].id.clone();
+    assert!(anchor.starts_with("#^"));
+    assert_eq!(
+        convert(
+            "Page",
+            "- {{embed ((6511c1e5-1111-4fd6-9c22-0b8f2ba6a111))}}\n- {{embed ((6511c1e5-2222-4fd6-9c22-0b8f2ba6a111))}}",
+            &mut data
+        ),
+        format!("![[Other#Setup]]\n\n![[Other{anchor}]]\n")
+    );
+    assert_eq!(
+        convert(
+            "Other",
+            "- plain\n  id:: 6511c1e5-2222-4fd6-9c22-0b8f2ba6a111\n- {{embed ((6511c1e5-2222-4fd6-9c22-0b8f2ba6a111))}}",
+            &mut data
+        ),
+        format!("- plain {}\n\n![[{anchor}]]\n", &anchor[1..])
+    );
+    convert(
+        "Third",
+        "id:: 6511c1e5-3333-4fd6-9c22-0b8f2ba6a111\n\n- text",
+        &mut data,
+    );
+    assert_eq!(
+        convert(
+            "Page",
+            "- {{embed ((6511c1e5-3333-4fd6-9c22-0b8f2ba6a111))}}",
+            &mut data
+        ),
+        "![[Third]]\n"
+    );
+}
+
+#[test]
+fn test_only_embed() {
+    let mut data = TestData::default();
+    convert(
+        "Other",
+        "- plain\n  id:: 6511c1e5-1111-4fd6-9c22-0b8f2ba6a111",
+        &mut data,
+    );
+    let anchor = data.refs["6511c1e5-1111-4fd6-9c22-0b8f2ba6a111"].id.clone();
+    assert_eq!(
+        convert(
+            "Page",
+            "- a\n- {{embed ((6511c1e5-1111-4fd6-9c22-0b8f2ba6a111))}}\n- {{embed [[Other]]}}\n- b\n  - {{embed [[Other]]}}",
+            &mut data
+        ),
+        format!("- a\n\n![[Other{anchor}]]\n\n![[Other]]\n\n- b\n    - ![[Other]]\n")
+    );
+}
+
+#[test]
+fn test_non_latin_header_ref() {
+    let mut data = TestData::default();
+    convert(
+        "Other",
+        "- ## Заметки: [[Проект]]\n  id:: 6511c1e5-1111-4fd6-9c22-0b8f2ba6a111\n- ## 日本語の見出し\n  id:: 6511c1e5-2222-4fd6-9c22-0b8f2ba6a111",
+        &mut data,
+    );
+    assert_eq!(
+        data.refs["6511c1e5-1111-4fd6-9c22-0b8f2ba6a111"].id,
+        "#Заметки Проект"
+    );
+    assert_eq!(
+        data.refs["6511c1e5-2222-4fd6-9c22-0b8f2ba6a111"].id,
+        "#日本語の見出し"
+    );
+}
+
+#[test]
+fn test_missing_asset() {
+    let mut data = TestData {
+        missing_assets: vec!["../assets/nope.png"],
+        ..Default::default()
+    };
+    let text = "- ![nope](../assets/nope.png)\n- ![yes](../assets/yes.png)";
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "![nope](../assets/nope.png)\n\n![yes](../assets/yes.png)\n"
+    );
+    assert_eq!(data.assets, ["../assets/yes.png"]);
+    assert_eq!(data.warnings.len(), 1);
+}
+
+#[test]
+fn test_draws() {
+    let mut data = TestData::default();
+    let text =
+        "- [[draws/2022-10-01.excalidraw]]\n- ![sketch](../draws/b.excalidraw)\n- [[drawings]]";
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "- ![[../draws/2022-10-01.excalidraw]]\n- ![[../draws/b.excalidraw]]\n- [[drawings]]\n"
+    );
+    assert_eq!(
+        data.assets,
+        ["../draws/2022-10-01.excalidraw", "../draws/b.excalidraw"]
+    );
+}
+
+#[test]
+fn test_callout_style() {
+    let text = "- **Definition** #.v-self-border\n  second line\n  id:: 6511c1e5-1111-4fd6-9c22-0b8f2ba6a111\n\t- child one\n\t- child two";
+    let mut data = TestData::default();
+    let out = convert("Page", text, &mut data);
+    let anchor = data.refs["6511c1e5-1111-4fd6-9c22-0b8f2ba6a111"].id[1..].to_owned();
+    assert_eq!(
+        out,
+        format!(
+            "```ad-def\n**Definition**\nsecond line\n- child one\n- child two\n```\n\n{anchor}\n"
+        )
+    );
+
+    data.config.callout_style = CalloutStyle::Native;
+    assert_eq!(
+        convert("Page", text, &mut data),
+        format!("> [!note]\n> **Definition**\n> second line\n> - child one\n> - child two\n\n{anchor}\n")
+    );
+}
+
+#[test]
+fn test_hard_breaks() {
+    let text = "- **Address**\n  Main Street 1\n  Springfield\n- roses are red\n  violets are blue\n  ```\n  code\n  more\n  ```";
+    let mut data = TestData::default();
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "**Address**\nMain Street 1\nSpringfield\n\n- roses are red\n  violets are blue\n  ```\n  code\n  more\n  ```\n"
+    );
+    data.config.hard_breaks = true;
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "**Address**\\\nMain Street 1\\\nSpringfield\n\n- roses are red\n  violets are blue\n  ```\n  code\n  more\n  ```\n"
+    );
+}
+
+#[test]
+fn test_list_item_continuation() {
+    let block = Block {
+        text: "- first line\nsecond line\n  third line".to_owned(),
+        is_list_item: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        block.to_string(true, &TestData::default()),
+        "- first line\n  second line\n  third line\n"
+    );
+    assert_eq!(
+        convert(
+            "Page",
+            "- see\n  #+BEGIN_QUERY\n  {:query [:find ?b]}\n  #+END_QUERY",
+            &mut TestData::default()
+        ),
+        "- see\n  %% logseq query:\n  {:query [:find ?b]}\n  %%\n"
+    );
+}
+
+#[test]
+fn test_keep_logseq_ids() {
+    let mut data = TestData {
+        config: Config {
+            keep_logseq_ids: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let text = "- some block\n  id:: 6511c1e5-1111-4fd6-9c22-0b8f2ba6a111\n- **Def** #.v-self-border\n  id:: 6511c1e5-2222-4fd6-9c22-0b8f2ba6a111";
+    let out = convert("Page", text, &mut data);
+    let a = &data.refs["6511c1e5-1111-4fd6-9c22-0b8f2ba6a111"].id[1..];
+    let b = &data.refs["6511c1e5-2222-4fd6-9c22-0b8f2ba6a111"].id[1..];
+    assert_eq!(
+        out,
+        format!("- some block %% logseq-id: 6511c1e5-1111-4fd6-9c22-0b8f2ba6a111 %% {a}\n\n```ad-def\n**Def**\n%% logseq-id: 6511c1e5-2222-4fd6-9c22-0b8f2ba6a111 %%\n```\n\n{b}\n")
+    );
+}
+
+#[test]
+fn test_colored_blocks() {
+    let text =
+        "- good\n  background-color:: green\n  - child\n- bad\n  background-color:: red\n- plain";
+    assert_eq!(
+        convert("Page", text, &mut TestData::default()),
+        "> [!tip]\n> - good\n>     - child\n\n> [!danger]\n> - bad\n\n- plain\n"
+    );
+}
+
+#[test]
+fn test_nested_callouts() {
+    let text =
+        "- outer #.v-self-border\n  - inner #.v-self-border\n    - ```\n      code\n      ```";
+    assert_eq!(
+        convert("Page", text, &mut TestData::default()),
+        "`````ad-def\n- outer\n    ````ad-def\n    - inner\n        - ```\n          code\n          ```\n    ````\n`````\n"
+    );
+}
 
-        let id = id.map(|id| {
-            let obsdn_id = if let Some(header) = &header {
-                let h = header_san_re().replace_all(&header, " ").trim().to_string();
-                format!("#{h}")
-            } else {
-                let mut hasher = DefaultHasher::new();
-                body.hash(&mut hasher);
-                let hash = hasher.finish();
-                format!("^{hash:x}")
-            };
+#[test]
+fn test_inline_code_ref_untouched() {
+    let mut data = TestData::default();
+    convert(
+        "Other",
+        "- target\n  id:: 6511c1e5-1111-4fd6-9c22-0b8f2ba6a111",
+        &mut data,
+    );
+    let anchor = data.refs["6511c1e5-1111-4fd6-9c22-0b8f2ba6a111"].id.clone();
+    assert_eq!(
+        convert(
+            "Page",
+            "- Type `((6511c1e5-1111-4fd6-9c22-0b8f2ba6a111))` to get ((6511c1e5-1111-4fd6-9c22-0b8f2ba6a111))",
+            &mut data
+        ),
+        format!("- Type `((6511c1e5-1111-4fd6-9c22-0b8f2ba6a111))` to get [[Other{anchor}]]\n")
+    );
+}
 
-            let id = Id {
-                obsdn_id,
-                logseq_id: id.to_string(),
-            };
-            data.register_id(&id);
-            id
-        });
+#[test]
+fn test_code_untouched() {
+    let text = "- Example `((6511c1e5-1111-4fd6-9c22-0b8f2ba6a111))`\n  ```bash\n  echo '![foo](bar)'\n  ```\n- ```\n  ^^not a highlight^^\n  ```";
+    let mut data = TestData::default();
+    convert(
+        "Other",
+        "- target\n  id:: 6511c1e5-1111-4fd6-9c22-0b8f2ba6a111",
+        &mut data,
+    );
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "- Example `((6511c1e5-1111-4fd6-9c22-0b8f2ba6a111))`\n  ```bash\n  echo '![foo](bar)'\n  ```\n- ```\n  ^^not a highlight^^\n  ```\n"
+    );
+    assert!(data.assets.is_empty());
+}
 
-        let children = if let Some(first_child) = first_child {
-            let num_spaces = first_child
-                .chars()
-                .take_while(|c| *c == ' ' || *c == '\t')
-                .count();
-            let c = first_child.trim_start().chars().next().unwrap();
+#[test]
+fn test_leading_standalone_ref() {
+    let mut data = TestData::default();
+    convert(
+        "Other",
+        "- foo\n  id:: 6511c1e5-1111-4fd6-9c22-0b8f2ba6a111",
+        &mut data,
+    );
+    let anchor = data.refs["6511c1e5-1111-4fd6-9c22-0b8f2ba6a111"].id.clone();
+    data.title = "Page".to_owned();
+    let mut block =
+        Block::parse("((6511c1e5-1111-4fd6-9c22-0b8f2ba6a111)) bar", &mut data).unwrap();
+    block.transform(None, None, &mut data);
+    assert_eq!(block.text, format!("[[Other{anchor}]] bar"));
+}
 
-            let lines = unsafe { union_str(first_child, text) }
-                .lines()
-                .map(|l| trim_start_up_to(num_spaces, l));
+#[test]
+fn test_same_page_refs() {
+    let text = "- ## Setup\n  id:: 6511c1e5-1111-4fd6-9c22-0b8f2ba6a111\n- foo\n  id:: 6511c1e5-2222-4fd6-9c22-0b8f2ba6a222\n- see ((6511c1e5-1111-4fd6-9c22-0b8f2ba6a111)) and ((6511c1e5-2222-4fd6-9c22-0b8f2ba6a222))";
+    let mut data = TestData::default();
+    let out = convert("Page", text, &mut data);
+    let anchor = data.refs["6511c1e5-2222-4fd6-9c22-0b8f2ba6a222"].id[1..].to_owned();
+    assert_eq!(
+        out,
+        format!("# Setup\n\n- foo {anchor}\n- see [[#Setup]] and [[#{anchor}]]\n")
+    );
+}
 
-            blocks(lines, c)
-                .into_iter()
-                .filter(|s| !s.trim().is_empty())
-                .map(|l| Block::parse(&l, data))
-                .try_collect()?
-        } else {
-            vec![]
-        };
+#[test]
+fn test_unresolved_ref() {
+    let text = "- see ((6511c1e5-1111-4fd6-9c22-0b8f2ba6a111))";
+    let mut data = TestData::default();
+    assert_eq!(convert("Page", text, &mut data), format!("{text}\n"));
+    assert_eq!(
+        data.warnings,
+        ["Unresolved block reference ((6511c1e5-1111-4fd6-9c22-0b8f2ba6a111)) in 'Page'"]
+    );
+}
 
-        Ok(Self {
-            text: body,
-            header,
-            children,
-            id: id.map(Into::into),
-            self_border,
-            is_list_item,
-        })
-    }
+#[test]
+fn test_journal_links() {
+    let mut data = TestData::default();
+    data.journal_links
+        .insert("Oct 5th, 2023".to_owned(), "2023-10-05".to_owned());
+    assert_eq!(
+        convert(
+            "Page",
+            "- on [[Oct 5th, 2023]] see [[Other]]\n- {{embed [[Oct 5th, 2023]]}}",
+            &mut data
+        ),
+        "- on [[2023-10-05]] see [[Other]]\n\n![[2023-10-05]]\n"
+    );
+}
 
-    pub fn set_list_item(&mut self, is_list_item: bool) {
-        if is_list_item == self.is_list_item {
-            return;
-        }
-        self.is_list_item = is_list_item;
-        if is_list_item {
-            self.text = normal_to_list_item(&self.text);
-        } else {
-            self.text = list_item_to_normal(&self.text);
-        }
-    }
+#[test]
+fn test_alias_links() {
+    let mut data = TestData::default();
+    convert("Canonical", "alias:: Old Name, other\n\n- text", &mut data);
+    assert_eq!(
+        convert(
+            "Page",
+            "- see [[Old Name]], [[Other]] and [[Canonical]]\n- [[Unknown]]",
+            &mut data
+        ),
+        "- see [[Canonical|Old Name]], [[Canonical|Other]] and [[Canonical]]\n- [[Unknown]]\n"
+    );
+}
 
-    pub fn to_string(&self, is_last: bool) -> String {
-        let n = self.children.len().saturating_sub(1);
+#[test]
+fn test_title_heading() {
+    let mut data = TestData {
+        config: Config {
+            title_heading: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    assert_eq!(
+        convert("My Page", "tags:: a\n\n- text", &mut data),
+        "---\ntags: [a]\n---\n\n# My Page\n\n- text\n"
+    );
+    assert_eq!(
+        convert("My Page", "- # My Page\n- text", &mut data),
+        "# My Page\n\n- text\n"
+    );
+    assert_eq!(
+        convert("My Page", "- # Other\n- ---", &mut data),
+        "# My Page\n\n# Other\n\n---\n"
+    );
+}
 
-        let children = self
-            .children
-            .iter()
-            .enumerate()
-            .map(|(i, c)| {
-                let indent = if c.is_list_item && self.is_list_item {
-                    repeat_space(4)
-                } else if self.is_list_item {
-                    repeat_space(2)
-                } else {
-                    repeat_space(0)
-                };
-                c.to_string(i == n)
-                    .split("\n")
-                    .map(|l| format!("{indent}{l}",))
-                    .join("\n")
-            })
-            .collect_vec()
-            .join("\n");
-        let text = &self.text;
-        let id = self
-            .id
-            .as_ref()
-            .and_then(|id| {
-                if self.header.is_some() {
-                    return None;
-                }
-                if self.self_border {
-                    Some(format!("\n{}\n", id.obsdn_id))
-                } else {
-                    Some(format!(" {}", id.obsdn_id))
-                }
-            })
-            .unwrap_or_default();
+#[test]
+fn test_invisible_spaces() {
+    let text = "title::\u{a0}Page\n\n-\u{a0}a\u{200b}b #\u{200b}tag\n  id::\u{a0}6511c1e5-1111-4fd6-9c22-0b8f2ba6a111\n- ```\n  a\u{a0}b\u{200b}\n  ```";
+    let mut data = TestData::default();
+    assert_eq!(
+        convert("Page", text, &mut data),
+        format!(
+            "- ab #tag {}\n- ```\n  a\u{a0}b\u{200b}\n  ```\n",
+            &data.refs["6511c1e5-1111-4fd6-9c22-0b8f2ba6a111"].id[1..]
+        )
+    );
+    assert_eq!(data.title, "Page");
+}
 
-        let before = (!self.children.is_empty())
-            .then_some("\n")
-            .unwrap_or_default();
-        let after = (is_last && self.children.is_empty())
-            .then_some("\n")
-            .unwrap_or_default();
+#[test]
+fn test_hashtags() {
+    let text = "- ## Heading\n- about #[[My Project]] and #rust";
+    let mut data = TestData::default();
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "# Heading\n\n- about #My-Project and #rust\n"
+    );
 
-        if self.self_border {
-            let children = children.trim_end();
+    data.config.tags_as_links = true;
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "# Heading\n\n- about [[My Project]] and #rust\n"
+    );
+}
 
-            format!("```ad-def\n{text}{before}{children}\n```\n{id}")
-        } else {
-            format!("{text}{id}{before}{children}{after}")
-        }
-    }
+#[test]
+fn test_image_dimensions() {
+    let text = "- ![a](../assets/a.png){:height 200, :width 400}\n- ![b](../assets/b.png){:width 300}\n- ![c](../assets/c.png){:height 100}\n- ![d](../assets/d.png)";
+    assert_eq!(
+        convert("Page", text, &mut TestData::default()),
+        "![[../assets/a.png|400]]\n\n![[../assets/b.png|300]]\n\n![c](../assets/c.png)\n\n![d](../assets/d.png)\n"
+    );
 }
 
-#[derive(strum::EnumIter, strum::AsRefStr, Debug, Clone, Copy)]
-pub enum Prop {
-    #[strum(serialize = "title::")]
-    Title,
-    #[strum(serialize = "alias::")]
-    Alias,
-    #[strum(serialize = "id::")]
-    Id,
-    #[strum(serialize = "collapsed::")]
-    Collapsed,
+#[test]
+fn test_image_embeds() {
+    let text = "- ![alt](../assets/a.png)\n- text ![](../assets/b.png)";
+    let mut data = TestData {
+        config: Config {
+            image_embeds: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "![[../assets/a.png|alt]]\n\n- text ![[../assets/b.png]]\n"
+    );
 }
-fn parse_prop(line: &str) -> Option<(Prop, &str)> {
-    let line = line.trim();
 
-    for e in Prop::iter() {
-        if let Some(suffix) = line.strip_prefix(e.as_ref()) {
-            return Some((e, suffix.trim_start()));
-        }
-    }
-    None
+#[test]
+fn test_image_captions() {
+    let text = "- ![a](../assets/a.png){:caption \"A cat\"}\n- ![b](../assets/b.png){:width 300, :caption \"A dog\"}\n- ![c](../assets/c.png)\n  _A bird_\n- ![d](../assets/d.png)";
+    let mut data = TestData::default();
+    data.config.italic_captions = true;
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "![A cat](../assets/a.png)\n\n![[../assets/b.png|A dog|300]]\n\n![A bird](../assets/c.png)\n\n![d](../assets/d.png)\n"
+    );
+    data.config.captions_below = true;
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "![a](../assets/a.png)\n*A cat*\n\n![[../assets/b.png|300]]\n*A dog*\n\n![c](../assets/c.png)\n*A bird*\n\n![d](../assets/d.png)\n"
+    );
+
+    // Italic lines below images are only captions with `italic_captions`.
+    let text = "- ![c](../assets/c.png)\n  _not a caption_";
+    assert_eq!(
+        convert("Page", text, &mut TestData::default()),
+        "![c](../assets/c.png)\n_not a caption_\n"
+    );
 }
 
-impl Page {
-    pub fn to_string(&self) -> String {
-        let blocks = self.blocks.iter().map(|b| b.to_string(true)).join("\n");
+#[test]
+fn test_linked_file_copied() {
+    let text = "- [Paper]([[../assets/paper.pdf]]) and [Other]([[Some Page]])";
+    let mut data = TestData::default();
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "- [[../assets/paper.pdf|Paper]] and [[Some Page|Other]]\n"
+    );
+    assert_eq!(data.assets, ["../assets/paper.pdf"]);
+}
 
-        let alias = if !self.alias.is_empty() {
-            format!("---\naliases: [{}]\n---\n\n", self.alias.join(", "))
-        } else {
-            String::new()
-        };
+#[test]
+fn test_math_untouched() {
+    let text = "- $$\\left(\\left(x\\right)\\right) + [[a]]$$\n- costs $5, or $((6511c1e5-1111-4fd6-9c22-0b8f2ba6a111))$ inline";
+    let mut data = TestData::default();
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "$$\\left(\\left(x\\right)\\right) + [[a]]$$\n\n- costs $5, or $((6511c1e5-1111-4fd6-9c22-0b8f2ba6a111))$ inline\n"
+    );
+    assert!(data.warnings.is_empty());
+}
 
-        format!("{alias}{blocks}")
-    }
+#[test]
+fn test_markdown_links_untouched() {
+    let text = "- [a [b] ^^c^^](https://en.wikipedia.org/wiki/A_(b)#[[x]]) and [notes](notes/page.md) and [Page]([[Some Page]])";
+    let mut data = TestData::default();
+    assert_eq!(
+        convert("Page", text, &mut data),
+        "- [a [b] ==c==](https://en.wikipedia.org/wiki/A_(b)#[[x]]) and [notes](notes/page.md) and [[Some Page|Page]]\n"
+    );
+    assert!(data.assets.is_empty());
+}
 
-    pub fn parse(file_name: &Path, text: &str, data: &mut dyn Data) -> Result<Self> {
-        let (title, alias) = {
-            let mut title = None;
-            let mut alias = vec![];
+#[test]
+fn test_trim_start_up_to() {
+    assert_eq!(trim_start_up_to(2, "   a"), " a");
+    assert_eq!(trim_start_up_to(0, "   a"), "   a");
+    assert_eq!(trim_start_up_to(1, "   a"), "  a");
+    assert_eq!(trim_start_up_to(3, "  a"), "a");
+}
 
-            for (prop, val) in text
-                .lines()
-                .filter(|l| !l.is_empty())
-                .take_while(|l| !l.trim_start().starts_with('-'))
-                .filter_map(|l| parse_prop(l))
-            {
-                match prop {
-                    Prop::Alias => alias.push(val.to_string()),
-                    Prop::Title => title = Some(val.to_string()),
-                    _ => (),
-                }
+/// Replaces all matches of `re` in `text` with placeholders, so that they are
+/// left alone by the transform passes, matches of group 1 stay as they are.
+/// Returns the replaced regions for [`unmask`].
+fn mask(text: &mut String, re: &Regex) -> Vec<String> {
+    let mut regions = vec![];
+    let masked = re
+        .replace_all(text, |c: &regex::Captures| {
+            if c.get(1).is_some() {
+                return c[0].to_owned();
             }
-            (
-                title.unwrap_or(
-                    file_name
-                        .file_stem()
-                        .unwrap_or(OsStr::new(""))
-                        .to_string_lossy()
-                        .into(),
-                ),
-                alias,
-            )
-        };
-        data.page_title(&title);
-
-        let lines = text
-            .lines()
-            .skip_while(|l| l.trim().is_empty() || !l.starts_with("-"));
+            regions.push(c[0].to_owned());
+            format!("\u{E000}{}\u{E001}", regions.len() - 1)
+        })
+        .to_string();
+    *text = masked;
+    regions
+}
 
-        let blocks: Vec<_> = blocks(lines, '-')
-            .into_iter()
-            .map(|l| Block::parse(&l, data))
-            .try_collect()?;
+/// Ends every line of the paragraph `text` that is followed by another one
+/// with a hard line break, code blocks, math blocks, tables and quotes are
+/// kept as they are.
+fn hard_breaks(text: &str) -> String {
+    let mut in_code = false;
+    let mut in_math = false;
+    let mut lines = text.lines().collect_vec();
+    let mut result = vec![];
+    lines.push("");
+    for (line, next) in lines.into_iter().tuple_windows() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("```") {
+            in_code = !in_code;
+        } else if trimmed == "$$" {
+            in_math = !in_math;
+        }
+        let plain = |l: &str| {
+            let l = l.trim_start();
+            !l.is_empty() && !l.starts_with(['|', '>', '#', '`', '$']) && !l.starts_with("- ")
+        };
+        match !in_code && !in_math && plain(line) && plain(next) {
+            true => result.push(format!("{}\\", line.trim_end())),
+            false => result.push(line.to_owned()),
+        }
+    }
+    result.join("\n")
+}
 
-        Ok(Self {
-            title,
-            alias,
-            blocks,
+/// Restores the regions replaced by [`mask`].
+fn unmask(text: &str, regions: &[String]) -> String {
+    mask_placeholder_re()
+        .replace_all(text, |c: &regex::Captures| {
+            regions[c[1].parse::<usize>().unwrap()].clone()
         })
-    }
+        .to_string()
+}
 
-    pub fn transform(&mut self, data: &mut dyn Data) {
-        for i in 0..self.blocks.len() {
-            let (prev, rest) = self.blocks.as_mut_slice().split_at_mut(i);
-            let (curr, _rest) = rest.split_at_mut(1);
-            let curr = curr.first_mut().unwrap();
-            curr.transform(None, prev.last(), data);
+/// Removes all `:NAME:` ... `:END:` drawers (e.g. `:LOGBOOK:`) from `lines`,
+/// returns the remaining lines and the lines of the removed drawers.
+fn strip_drawers<'a>(lines: impl Iterator<Item = &'a str>) -> (Vec<&'a str>, Vec<&'a str>) {
+    let mut result = vec![];
+    let mut drawers = vec![];
+    let mut drawer: Option<Vec<&str>> = None;
+    for line in lines {
+        match &mut drawer {
+            Some(d) if line.trim() == ":END:" => {
+                drawers.append(d);
+                drawer = None;
+            }
+            Some(d) => d.push(line),
+            None if drawer_re().is_match(line) => drawer = Some(vec![line]),
+            None => result.push(line),
         }
     }
+    // Keep unterminated drawers as they are.
+    result.extend(drawer.unwrap_or_default());
+    (result, drawers)
 }
 
-fn blocks<'a>(lines: impl Iterator<Item = &'a str> + 'a, delim: char) -> Vec<String> {
-    let mut lines = lines.peekable();
-    let mut lines_accu: Option<String> = None;
+#[test]
+fn test_strip_drawers() {
+    let text = "DONE task\n:LOGBOOK:\nCLOCK: [2023-10-05 Thu 10:00]--[2023-10-05 Thu 11:00] =>  01:00:00\n:END:\nafter";
+    let (lines, drawers) = strip_drawers(text.lines());
+    assert_eq!(lines, ["DONE task", "after"]);
+    assert_eq!(drawers.len(), 2);
+    assert_eq!(
+        strip_drawers(":LOGBOOK:\nfoo".lines()).0,
+        [":LOGBOOK:", "foo"]
+    );
+}
 
-    let mut result = Vec::<String>::new();
+#[test]
+fn test_collapsed() {
+    let mut data = TestData::default();
+    let block = Block::parse("- foo\n  collapsed:: true\n  - bar", &mut data).unwrap();
+    assert!(block.collapsed);
+    assert_eq!(block.text, "- foo");
 
-    while let Some(line) = lines.next() {
-        if line.starts_with(delim) {
-            if let Some(accu) = lines_accu.take() {
-                result.push(accu);
-            }
-            lines_accu = Some(String::with_capacity(line.len()));
-        }
-        if let Some(accu) = &mut lines_accu {
-            if !accu.is_empty() {
-                accu.push('\n');
-            }
-            accu.push_str(line);
-        }
+    let block = Block::parse("- foo\n  collapsed:: false\n  - bar", &mut data).unwrap();
+    assert!(!block.collapsed);
+    assert_eq!(block.text, "- foo");
+}
 
-        if let None = lines.peek() {
-            let accu = lines_accu.take().unwrap_or_else(|| line.to_string());
-            result.push(accu);
-        }
-    }
+#[test]
+fn test_ordered_list() {
+    let mut data = TestData::default();
+    let text = "- list\n  - one\n    logseq.order-list-type:: number\n    - nested\n      logseq.order-list-type:: number\n  - two\n    logseq.order-list-type:: number\n  - three\n    logseq.order-list-type:: number";
+    assert_eq!(
+        convert("Foo", text, &mut data),
+        "- list\n    1. one\n        1. nested\n\n    2. two\n    3. three\n"
+    );
+}
 
-    result
+/// 64-bit FNV-1a hash, which unlike `DefaultHasher` is guaranteed to stay the
+/// same across runs and Rust versions.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
 }
 
-unsafe fn union_str<'a>(first: &'a str, last: &'a str) -> &'a str {
-    let len = last.as_ptr().offset(last.len() as isize) as usize - first.as_ptr() as usize;
-    std::str::from_utf8_unchecked(std::slice::from_raw_parts(first.as_ptr(), len))
+/// Returns an anchor made of the first words of `text`, lowercased and joined
+/// by dashes. Only ASCII letters and digits are kept, as Obsidian allows
+/// nothing else in block ids.
+fn slug(text: &str) -> String {
+    text.strip_prefix("- ")
+        .unwrap_or(text)
+        .to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .take(4)
+        .join("-")
 }
 
-fn trim_start_up_to(n: usize, s: &str) -> &str {
-    let idx = s
-        .char_indices()
-        .take(n)
-        .take_while(|(_, c)| c.is_whitespace())
-        .last()
-        .map(|(i, c)| (i + c.len_utf8()).min(s.len() - 1))
-        .unwrap_or(0);
-    &s[idx..]
+#[test]
+fn test_slug_anchors() {
+    let mut data = TestData {
+        config: Config {
+            anchor_style: AnchorStyle::Slug,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let text = "- Buy milk and eggs today\n  id:: 6511c1e5-1111-4fd6-9c22-0b8f2ba6a111\n- Buy milk and eggs tomorrow\n  id:: 6511c1e5-2222-4fd6-9c22-0b8f2ba6a111\n  - 日本語\n    id:: 6511c1e5-3333-4fd6-9c22-0b8f2ba6a111";
+    let out = convert("Page", text, &mut data);
+    assert_eq!(
+        data.refs["6511c1e5-1111-4fd6-9c22-0b8f2ba6a111"].id,
+        "#^buy-milk-and-eggs"
+    );
+    assert_eq!(
+        data.refs["6511c1e5-2222-4fd6-9c22-0b8f2ba6a111"].id,
+        "#^buy-milk-and-eggs-1"
+    );
+    // Without any latin words the hash is used.
+    let hash = &data.refs["6511c1e5-3333-4fd6-9c22-0b8f2ba6a111"].id;
+    assert!(hash.len() > 10, "{hash}");
+    assert!(
+        out.starts_with("- Buy milk and eggs today ^buy-milk-and-eggs\n"),
+        "{out}"
+    );
 }
 
 #[test]
-fn test_trim_start_up_to() {
-    assert_eq!(trim_start_up_to(2, "   a"), " a");
-    assert_eq!(trim_start_up_to(0, "   a"), "   a");
-    assert_eq!(trim_start_up_to(1, "   a"), "  a");
-    assert_eq!(trim_start_up_to(3, "  a"), "a");
+fn test_block_anchor_stable() {
+    let mut data = TestData::default();
+    let block = Block::parse(
+        "- some block\n  id:: 6511c1e5-1111-4fd6-9c22-0b8f2ba6a111",
+        &mut data,
+    )
+    .unwrap();
+    assert_eq!(block.id.unwrap().obsdn_id, "^71319177cfda2d71");
 }
 
 fn list_item_to_normal(s: &str) -> String {
-    let b = s.strip_prefix("- ").unwrap();
+    // Empty list items may have lost the space after the bullet.
+    let b = s
+        .strip_prefix("- ")
+        .or_else(|| s.strip_prefix('-'))
+        .unwrap_or(s);
     let mut lines = b.lines();
-    let first_line = lines.next().unwrap();
+    let first_line = lines.next().unwrap_or_default();
     std::iter::once(first_line)
         .chain(lines.map(|l| trim_start_up_to(2, l)))
         .join("\n")
@@ -579,16 +4295,106 @@ fn list_item_to_normal(s: &str) -> String {
 
 fn normal_to_list_item(s: &str) -> String {
     let mut lines = s.lines();
-    let mut result = format!("- {}", lines.next().unwrap());
-    result.extend(lines.flat_map(|l| ["\n", repeat_space(2), l]));
+    let mut result = format!("- {}", lines.next().unwrap_or_default());
+    let indent = repeat_space(2);
+    result.extend(lines.flat_map(|l| ["\n", &indent, l]));
     result
 }
 
-fn repeat_space(n: usize) -> &'static str {
+/// `n` spaces, only deeper indents than the lookup table allocate.
+fn repeat_space(n: usize) -> Cow<'static, str> {
     const LUT: &str = "                ";
     if n > LUT.len() {
-        unimplemented!()
+        Cow::Owned(" ".repeat(n))
     } else {
-        &LUT[0..n]
+        Cow::Borrowed(&LUT[0..n])
+    }
+}
+
+#[cfg(test)]
+#[derive(Default)]
+struct TestData {
+    title: String,
+    refs: BTreeMap<String, Ref>,
+    assets: Vec<String>,
+    missing_assets: Vec<&'static str>,
+    warnings: Vec<String>,
+    config: Config,
+    texts: BTreeMap<String, String>,
+    journal_links: BTreeMap<String, String>,
+    aliases: BTreeMap<String, String>,
+    traces: Option<Vec<String>>,
+    /// The referenced ids, if only their anchors are written.
+    referenced: Option<Vec<String>>,
+}
+
+#[cfg(test)]
+impl Data for TestData {
+    fn page_title(&mut self, title: &str) {
+        self.title = title.trim().to_owned();
+    }
+    fn copy_asset(&mut self, path: &str) -> Result<String> {
+        if self.missing_assets.contains(&path) {
+            anyhow::bail!("No such file");
+        }
+        self.assets.push(path.to_owned());
+        Ok(path.to_owned())
     }
+    fn warn(&mut self, message: &str) {
+        self.warnings.push(message.to_owned());
+    }
+    fn tracing(&self) -> bool {
+        self.traces.is_some()
+    }
+    fn trace(&mut self, pass: &str, _before: &str, _after: &str) {
+        self.traces.as_mut().unwrap().push(pass.to_owned());
+    }
+    fn register_id(&mut self, id: &Id) {
+        self.texts.insert(id.logseq_id.clone(), id.text.clone());
+        let hash = if id.obsdn_id.starts_with('^') {
+            "#"
+        } else {
+            ""
+        };
+        self.refs.insert(
+            id.logseq_id.clone(),
+            Ref {
+                file: self.title.clone(),
+                id: format!("{hash}{}", id.obsdn_id),
+            },
+        );
+    }
+    fn query_id(&self, logseq_id: &str) -> Option<&Ref> {
+        self.refs.get(logseq_id)
+    }
+    fn curr_title(&self) -> &str {
+        &self.title
+    }
+    fn query_block_text(&self, logseq_id: &str) -> Option<&str> {
+        self.texts.get(logseq_id).map(String::as_str)
+    }
+    fn journal_link(&self, page: &str) -> Option<String> {
+        self.journal_links.get(page).cloned()
+    }
+    fn register_alias(&mut self, alias: &str) {
+        self.aliases
+            .insert(alias.to_lowercase(), self.title.clone());
+    }
+    fn query_alias(&self, alias: &str) -> Option<&str> {
+        self.aliases.get(&alias.to_lowercase()).map(String::as_str)
+    }
+    fn is_referenced(&self, logseq_id: &str) -> bool {
+        (self.referenced.as_ref()).is_none_or(|r| r.iter().any(|id| id == logseq_id))
+    }
+    fn config(&self) -> &Config {
+        &self.config
+    }
+}
+
+/// Parses, transforms and renders the page `text` named `title`.
+#[cfg(test)]
+fn convert(title: &str, text: &str, data: &mut TestData) -> String {
+    let mut page = Page::parse(Path::new(&format!("{title}.md")), text, data).unwrap();
+    page.transform(data);
+    page.to_string(data)
 }
@@ -0,0 +1,34 @@
+//! Passes that run on a [`Page`] after [`Page::transform`].
+//!
+//! Post-processors are registered in [`Config::post_processors`](crate::Config::post_processors)
+//! and run in order.
+
+use std::fmt::Debug;
+
+use itertools::Itertools;
+
+use crate::page::{Block, Page};
+
+/// A pass over a transformed page, before it is rendered.
+pub trait PostProcess: Debug + Send + Sync {
+    fn process(&self, page: &mut Page);
+}
+
+/// Removes the trailing whitespace of every line of the blocks.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TrimTrailingWhitespace;
+
+impl PostProcess for TrimTrailingWhitespace {
+    fn process(&self, page: &mut Page) {
+        fn trim(block: &mut Block) {
+            let text = block.text.lines().map(str::trim_end).join("\n");
+            // The bullet of an empty list item needs its space.
+            block.text = match block.is_list_item && text == "-" {
+                true => "- ".to_owned(),
+                false => text,
+            };
+            block.children.iter_mut().for_each(trim);
+        }
+        page.blocks.iter_mut().for_each(trim);
+    }
+}
@@ -0,0 +1,26 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn test_dir(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_no_debug_output() {
+    let dir = test_dir("cli-no-debug-output");
+    let page = dir.join("Page.md");
+    std::fs::write(&page, "- hello\n  - world").unwrap();
+
+    let out = Command::new(env!("CARGO_BIN_EXE_logseq2obsdn"))
+        .arg(&page)
+        .arg(dir.join("vault"))
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(!stdout.contains("Block {"), "{stdout}");
+    assert!(dir.join("vault/Page.md").exists());
+}